@@ -0,0 +1,349 @@
+use nom::{
+    bytes::complete::{tag, take},
+    number::complete::{be_u16, be_u24, be_u64, le_u16, le_u32, u8 as byte},
+    error::Error,
+    IResult,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const VORBIS_IDENT_MAGIC: &[u8] = b"\x01vorbis";
+const VORBIS_COMMENT_MAGIC: &[u8] = b"\x03vorbis";
+const OPUS_HEAD_MAGIC: &[u8] = b"OpusHead";
+const OPUS_TAGS_MAGIC: &[u8] = b"OpusTags";
+const FLAC_MAPPING_MAGIC: &[u8] = b"\x7fFLAC";
+
+// Vorbis streams start with identification, comment and setup header packets
+const VORBIS_HEADER_PACKETS: usize = 3;
+// Opus streams start with OpusHead and OpusTags
+const OPUS_HEADER_PACKETS: usize = 2;
+
+const FLAC_BLOCK_LAST: u8 = 0x80;
+const FLAC_BLOCK_VORBIS_COMMENT: u8 = 4;
+
+// Opus always decodes at 48 kHz and its granule positions count 48 kHz samples
+const OPUS_SAMPLE_RATE: u32 = 48000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    Vorbis,
+    Opus,
+    Flac,
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::Vorbis => write!(f, "Vorbis"),
+            Codec::Opus => write!(f, "Opus"),
+            Codec::Flac => write!(f, "FLAC"),
+        }
+    }
+}
+
+/// Technical details of a logical stream, read from its identification header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioInfo {
+    pub codec: Codec,
+    pub channels: u8,
+    pub sample_rate: u32,
+    // Bitrates in bits per second, only Vorbis announces them
+    pub bitrate_nominal: Option<u32>,
+    pub bitrate_min: Option<u32>,
+    pub bitrate_max: Option<u32>,
+    // Only FLAC announces its sample size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bits_per_sample: Option<u8>,
+    // Human readable summary like "Vorbis 44.1 kHz stereo ~128 kbps"
+    pub summary: String,
+}
+
+impl AudioInfo {
+    fn new(codec: Codec, channels: u8, sample_rate: u32) -> Self {
+        Self {
+            codec,
+            channels,
+            sample_rate,
+            bitrate_nominal: None,
+            bitrate_min: None,
+            bitrate_max: None,
+            bits_per_sample: None,
+            summary: String::new(),
+        }
+    }
+
+    fn summarise(mut self) -> Self {
+        let channels = match self.channels {
+            1 => "mono".to_string(),
+            2 => "stereo".to_string(),
+            n => format!("{} channels", n),
+        };
+        self.summary = format!("{} {} kHz {}", self.codec, self.sample_rate as f64 / 1000.0, channels);
+        if let Some(bitrate) = self.bitrate_nominal {
+            self.summary += &format!(" ~{} kbps", bitrate / 1000);
+        }
+        self
+    }
+}
+
+/// Header phase of a logical stream, from its BOS page up to the first audio packet.
+pub struct StreamHeaders {
+    pub codec: Codec,
+    // None if the identification header could not be parsed
+    pub info: Option<AudioInfo>,
+    // Header packets seen so far, including the identification header
+    packets: usize,
+    // Total number of header packets, if the codec tells us up front
+    expected: Option<usize>,
+    finished: bool,
+}
+
+impl StreamHeaders {
+    /// Detects the codec from the identification header, the first packet of a logical stream.
+    pub fn detect(ident: &[u8]) -> Option<Self> {
+        let (codec, expected, info) = if ident.starts_with(VORBIS_IDENT_MAGIC) {
+            (Codec::Vorbis, Some(VORBIS_HEADER_PACKETS), vorbis_info(ident))
+        } else if ident.starts_with(OPUS_HEAD_MAGIC) {
+            let info = parse_opus_head(ident).ok().map(|(_, info)| info);
+            (Codec::Opus, Some(OPUS_HEADER_PACKETS), info)
+        } else {
+            let (input, count) = parse_flac_mapping(ident).ok()?;
+            let info = parse_flac_streaminfo(input).ok().map(|(_, info)| info);
+            // A count of zero means the number of metadata packets is unknown
+            (Codec::Flac, (count > 0).then_some(1 + count as usize), info)
+        };
+
+        Some(Self {
+            codec,
+            info: info.map(AudioInfo::summarise),
+            packets: 1,
+            expected,
+            finished: false,
+        })
+    }
+
+    /// Whether all header packets have been seen and audio data follows.
+    pub fn finished(&self) -> bool {
+        self.finished || self.expected.is_some_and(|expected| self.packets >= expected)
+    }
+
+    /// Feeds the next header packet of the stream.
+    ///
+    /// Returns the Vorbis comment structure (vendor string onwards) if this packet
+    /// is the comment header of the codec.
+    pub fn push<'a>(&mut self, data: &'a [u8]) -> Option<&'a [u8]> {
+        self.packets += 1;
+        match self.codec {
+            Codec::Vorbis => {
+                (self.packets == 2).then(|| data.strip_prefix(VORBIS_COMMENT_MAGIC)).flatten()
+            }
+            Codec::Opus => {
+                (self.packets == 2).then(|| data.strip_prefix(OPUS_TAGS_MAGIC)).flatten()
+            }
+            Codec::Flac => {
+                let (_, (block_type, body)) = parse_flac_block(data).ok()?;
+                if block_type & FLAC_BLOCK_LAST != 0 {
+                    self.finished = true;
+                }
+                (block_type & !FLAC_BLOCK_LAST == FLAC_BLOCK_VORBIS_COMMENT).then_some(body)
+            }
+        }
+    }
+}
+
+fn vorbis_info(ident: &[u8]) -> Option<AudioInfo> {
+    let header = lewton::header::read_header_ident(ident).ok()?;
+    // Zero or negative bitrates mean the encoder did not set them
+    let bitrate = |value: i32| u32::try_from(value).ok().filter(|&value| value > 0);
+    Some(AudioInfo {
+        bitrate_nominal: bitrate(header.bitrate_nominal),
+        bitrate_min: bitrate(header.bitrate_minimum),
+        bitrate_max: bitrate(header.bitrate_maximum),
+        ..AudioInfo::new(Codec::Vorbis, header.audio_channels, header.audio_sample_rate)
+    })
+}
+
+/// Parses the channel count of an OpusHead packet.
+fn parse_opus_head(input: &[u8]) -> IResult<&[u8], AudioInfo, Error<&[u8]>> {
+    let (input, _) = tag(OPUS_HEAD_MAGIC)(input)?;
+    let (input, _version) = byte(input)?;
+    let (input, channels) = byte(input)?;
+    // Pre-skip and the sample rate of the original input, which is informational only
+    let (input, _) = le_u16(input)?;
+    let (input, _) = le_u32(input)?;
+    Ok((input, AudioInfo::new(Codec::Opus, channels, OPUS_SAMPLE_RATE)))
+}
+
+/// Parses the STREAMINFO block that follows the Ogg FLAC mapping header.
+fn parse_flac_streaminfo(input: &[u8]) -> IResult<&[u8], AudioInfo, Error<&[u8]>> {
+    let (input, _block_header) = take(4usize)(input)?;
+    // Block and frame size limits
+    let (input, _) = take(10usize)(input)?;
+    // 20 bits sample rate, 3 bits channels - 1, 5 bits bits per sample - 1, 36 bits total samples
+    let (input, packed) = be_u64(input)?;
+    let sample_rate = (packed >> 44) as u32;
+    let channels = ((packed >> 41) & 0x7) as u8 + 1;
+    let bits_per_sample = ((packed >> 36) & 0x1f) as u8 + 1;
+    Ok((input, AudioInfo {
+        bits_per_sample: Some(bits_per_sample),
+        ..AudioInfo::new(Codec::Flac, channels, sample_rate)
+    }))
+}
+
+/// Parses the Ogg FLAC mapping header and returns the number of metadata header packets.
+fn parse_flac_mapping(input: &[u8]) -> IResult<&[u8], u16, Error<&[u8]>> {
+    let (input, _) = tag(FLAC_MAPPING_MAGIC)(input)?;
+    // Mapping major and minor version
+    let (input, _) = take(2usize)(input)?;
+    let (input, count) = be_u16(input)?;
+    let (input, _) = tag(b"fLaC")(input)?;
+    Ok((input, count))
+}
+
+// Block type byte (including the last-block flag) and block body
+type FlacBlock<'a> = (u8, &'a [u8]);
+
+/// Parses a FLAC metadata block into its type byte and body.
+fn parse_flac_block(input: &[u8]) -> IResult<&[u8], FlacBlock<'_>, Error<&[u8]>> {
+    let (input, block_type) = byte(input)?;
+    let (input, length) = be_u24(input)?;
+    let (input, body) = take(length)(input)?;
+    Ok((input, (block_type, body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vorbis_ident(channels: u8, sample_rate: u32, bitrates: [i32; 3]) -> Vec<u8> {
+        let mut packet = VORBIS_IDENT_MAGIC.to_vec();
+        packet.extend(0u32.to_le_bytes());
+        packet.push(channels);
+        packet.extend(sample_rate.to_le_bytes());
+        for bitrate in bitrates {
+            packet.extend(bitrate.to_le_bytes());
+        }
+        // Block sizes 2^8 and 2^11, then the framing bit
+        packet.extend([0xb8, 0x01]);
+        packet
+    }
+
+    fn opus_head(channels: u8) -> Vec<u8> {
+        let mut packet = OPUS_HEAD_MAGIC.to_vec();
+        packet.extend([1, channels]);
+        packet.extend(312u16.to_le_bytes());
+        packet.extend(44100u32.to_le_bytes());
+        packet.extend([0, 0, 0]);
+        packet
+    }
+
+    fn flac_block(block_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut block = vec![block_type];
+        block.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        block.extend(body);
+        block
+    }
+
+    fn flac_ident(count: u16, sample_rate: u32, channels: u8, bits_per_sample: u8) -> Vec<u8> {
+        let mut packet = FLAC_MAPPING_MAGIC.to_vec();
+        packet.extend([1, 0]);
+        packet.extend(count.to_be_bytes());
+        packet.extend(b"fLaC");
+        let mut streaminfo = vec![0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0];
+        let packed = (sample_rate as u64) << 44
+            | ((channels - 1) as u64) << 41
+            | ((bits_per_sample - 1) as u64) << 36
+            | 0xf_ffff_fffe;
+        streaminfo.extend(packed.to_be_bytes());
+        streaminfo.extend([0xaa; 16]);
+        packet.extend(flac_block(0, &streaminfo));
+        packet
+    }
+
+    #[test]
+    fn finds_the_vorbis_comment_header() {
+        let mut headers = StreamHeaders::detect(&vorbis_ident(2, 44100, [0, 128000, 0])).unwrap();
+        assert_eq!(headers.codec, Codec::Vorbis);
+        assert!(!headers.finished());
+
+        assert_eq!(headers.push(b"\x03vorbiscomments"), Some(b"comments".as_slice()));
+        assert!(!headers.finished());
+        assert_eq!(headers.push(b"\x05vorbissetup"), None);
+        assert!(headers.finished());
+    }
+
+    #[test]
+    fn finds_the_opus_tags() {
+        let mut headers = StreamHeaders::detect(&opus_head(2)).unwrap();
+        assert_eq!(headers.codec, Codec::Opus);
+        assert_eq!(headers.push(b"OpusTagscomments"), Some(b"comments".as_slice()));
+        assert!(headers.finished());
+    }
+
+    #[test]
+    fn counts_announced_flac_header_packets() {
+        let mut headers = StreamHeaders::detect(&flac_ident(2, 44100, 2, 16)).unwrap();
+        assert_eq!(headers.codec, Codec::Flac);
+        assert_eq!(headers.push(&flac_block(FLAC_BLOCK_VORBIS_COMMENT, b"comments")), Some(b"comments".as_slice()));
+        assert!(!headers.finished());
+        assert_eq!(headers.push(&flac_block(1, &[0; 8])), None);
+        assert!(headers.finished());
+    }
+
+    #[test]
+    fn ends_unannounced_flac_headers_on_the_last_block() {
+        let mut headers = StreamHeaders::detect(&flac_ident(0, 44100, 2, 16)).unwrap();
+        assert_eq!(headers.push(&flac_block(1, &[0; 8])), None);
+        assert_eq!(headers.push(&flac_block(FLAC_BLOCK_VORBIS_COMMENT, b"comments")), Some(b"comments".as_slice()));
+        assert!(!headers.finished());
+        assert_eq!(headers.push(&flac_block(FLAC_BLOCK_LAST | 1, &[0; 8])), None);
+        assert!(headers.finished());
+    }
+
+    #[test]
+    fn ignores_unknown_codecs() {
+        assert!(StreamHeaders::detect(b"Speex   ").is_none());
+        assert!(StreamHeaders::detect(b"").is_none());
+    }
+
+    #[test]
+    fn unpacks_flac_streaminfo() {
+        let info = StreamHeaders::detect(&flac_ident(1, 44100, 2, 16)).unwrap().info.unwrap();
+        assert_eq!((info.sample_rate, info.channels, info.bits_per_sample), (44100, 2, Some(16)));
+        assert_eq!(info.summary, "FLAC 44.1 kHz stereo");
+
+        // Maximum values of each field, so a shift off by one bleeds into its neighbour
+        let info = StreamHeaders::detect(&flac_ident(1, 0xf_ffff, 8, 32)).unwrap().info.unwrap();
+        assert_eq!((info.sample_rate, info.channels, info.bits_per_sample), (0xf_ffff, 8, Some(32)));
+
+        let info = StreamHeaders::detect(&flac_ident(1, 96000, 6, 24)).unwrap().info.unwrap();
+        assert_eq!((info.sample_rate, info.channels, info.bits_per_sample), (96000, 6, Some(24)));
+        assert_eq!(info.summary, "FLAC 96 kHz 6 channels");
+    }
+
+    #[test]
+    fn parses_opus_head() {
+        // Opus always decodes at 48 kHz, whatever the original input rate was
+        let info = StreamHeaders::detect(&opus_head(1)).unwrap().info.unwrap();
+        assert_eq!((info.sample_rate, info.channels), (OPUS_SAMPLE_RATE, 1));
+        assert_eq!(info.bits_per_sample, None);
+        assert_eq!(info.summary, "Opus 48 kHz mono");
+
+        let mut truncated = opus_head(2);
+        truncated.truncate(OPUS_HEAD_MAGIC.len() + 3);
+        assert!(StreamHeaders::detect(&truncated).unwrap().info.is_none());
+    }
+
+    #[test]
+    fn parses_vorbis_bitrates() {
+        let info = StreamHeaders::detect(&vorbis_ident(2, 44100, [0, 128000, -1])).unwrap().info.unwrap();
+        assert_eq!((info.sample_rate, info.channels), (44100, 2));
+        assert_eq!((info.bitrate_max, info.bitrate_nominal, info.bitrate_min), (None, Some(128000), None));
+        assert_eq!(info.summary, "Vorbis 44.1 kHz stereo ~128 kbps");
+
+        let info = StreamHeaders::detect(&vorbis_ident(1, 22050, [0, 0, 0])).unwrap().info.unwrap();
+        assert_eq!(info.bitrate_nominal, None);
+        assert_eq!(info.summary, "Vorbis 22.05 kHz mono");
+    }
+}
@@ -0,0 +1,363 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+
+/// Runtime configuration, read from a TOML file (`CONFIG`) or from environment variables.
+///
+/// ```toml
+/// listen = "0.0.0.0:3000"
+/// default_stream = "chiptune"
+/// cors_origins = ["https://krelez.ruohki.dev"]
+///
+/// [history]
+/// size = 100
+/// max_age = 86400
+/// file = "/data/history.jsonl"
+///
+/// [timing]
+/// retry_delay = 5
+/// retry_max_delay = 60
+/// retry_multiplier = 2.0
+/// retry_jitter = 0.2
+/// stall_after = 10
+/// connect_timeout = 10
+/// read_timeout = 30
+/// failover_after = 3
+/// failback_interval = 60
+/// debounce = 0
+/// ending_soon = 15
+///
+/// [webhooks]
+/// attempts = 5
+/// retry_delay = 1
+/// retry_max_delay = 60
+/// timeout = 10
+/// dead_letter = "/data/webhooks-failed.jsonl"
+///
+/// [[streams]]
+/// name = "chiptune"
+/// url = "https://cast.ruohki.services/chiptune.ogg"
+/// fallbacks = ["http://icecast:8000/chip.mp3"]
+///
+/// [[streams.webhooks]]
+/// url = "https://chat.example.com/hooks/now-playing"
+/// secret = "change-me"
+/// template = { text = "Now playing on {stream}: {display}" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    // Defaults to the first stream
+    default_stream: Option<String>,
+    // Empty or "*" allows any origin
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub timing: TimingConfig,
+    #[serde(default)]
+    pub webhooks: WebhookSettings,
+    pub streams: Vec<StreamConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamConfig {
+    pub name: String,
+    pub url: String,
+    // Mirrors tried in order when the primary url keeps failing
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+    // Notified of every metadata update
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Key for the HMAC-SHA256 signature header, requests are unsigned without it
+    pub secret: Option<String>,
+    // JSON fields of the body with `{field}` placeholders, the metadata is posted as is without it
+    #[serde(default)]
+    pub template: BTreeMap<String, String>,
+}
+
+impl StreamConfig {
+    /// The primary url followed by the fallbacks.
+    pub fn urls(&self) -> Vec<String> {
+        std::iter::once(&self.url).chain(&self.fallbacks).cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    // Number of tracks kept in memory per stream
+    pub size: usize,
+    // Seconds a finished track is kept in memory
+    pub max_age: u64,
+    // JSON lines play log, only read at startup
+    pub file: Option<String>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            size: 100,
+            max_age: 24 * 60 * 60,
+            file: None,
+        }
+    }
+}
+
+impl HistoryConfig {
+    pub fn max_age(&self) -> Duration {
+        Duration::from_secs(self.max_age)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingConfig {
+    // Seconds to wait before the first reconnect
+    pub retry_delay: u64,
+    // Upper bound for the reconnect delay in seconds
+    pub retry_max_delay: u64,
+    // Factor the delay grows by with every failed attempt
+    pub retry_multiplier: f64,
+    // Random spread of the delay, as a fraction of it
+    pub retry_jitter: f64,
+    // Seconds without data before a connected stream is reported as stalled, 0 disables it
+    pub stall_after: u64,
+    // Seconds to wait for the upstream to accept the connection, 0 disables it
+    pub connect_timeout: u64,
+    // Seconds without data before the connection is dropped and retried, 0 disables it
+    pub read_timeout: u64,
+    // Consecutive failed attempts on an upstream url before switching to the next one
+    pub failover_after: u32,
+    // Seconds between checks whether the primary url is back while on a fallback, 0 disables failback
+    pub failback_interval: u64,
+    // Minimum seconds between two track changes, later changes within it are dropped. 0 disables it
+    pub debounce: u64,
+    // Seconds before the end of a track with a known duration to announce it, 0 disables it
+    pub ending_soon: u64,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            retry_delay: 5,
+            retry_max_delay: 60,
+            retry_multiplier: 2.0,
+            retry_jitter: 0.2,
+            stall_after: 10,
+            connect_timeout: 10,
+            read_timeout: 30,
+            failover_after: 3,
+            failback_interval: 60,
+            debounce: 0,
+            ending_soon: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookSettings {
+    // Deliveries tried per update before giving up
+    pub attempts: u32,
+    // Seconds to wait before the first retry, growing like the upstream reconnect delay
+    pub retry_delay: u64,
+    // Upper bound for the retry delay in seconds
+    pub retry_max_delay: u64,
+    // Seconds a single request may take
+    pub timeout: u64,
+    // JSON lines file deliveries that failed for good are written to
+    pub dead_letter: Option<String>,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            retry_delay: 1,
+            retry_max_delay: 60,
+            timeout: 10,
+            dead_letter: None,
+        }
+    }
+}
+
+impl WebhookSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.max(1))
+    }
+
+    /// Retry timing, using the jitter and multiplier of the upstream reconnects.
+    pub fn retry_timing(&self, timing: &TimingConfig) -> TimingConfig {
+        TimingConfig {
+            retry_delay: self.retry_delay,
+            retry_max_delay: self.retry_max_delay,
+            ..timing.clone()
+        }
+    }
+}
+
+impl TimingConfig {
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_delay)
+    }
+
+    pub fn retry_max_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_max_delay.max(self.retry_delay))
+    }
+
+    pub fn stall_after(&self) -> Option<Duration> {
+        (self.stall_after > 0).then(|| Duration::from_secs(self.stall_after))
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        (self.connect_timeout > 0).then(|| Duration::from_secs(self.connect_timeout))
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        (self.read_timeout > 0).then(|| Duration::from_secs(self.read_timeout))
+    }
+
+    pub fn failback_interval(&self) -> Option<Duration> {
+        (self.failback_interval > 0).then(|| Duration::from_secs(self.failback_interval))
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_secs(self.debounce)
+    }
+
+    pub fn ending_soon(&self) -> Option<Duration> {
+        (self.ending_soon > 0).then(|| Duration::from_secs(self.ending_soon))
+    }
+}
+
+fn default_listen() -> String {
+    "0.0.0.0:3000".to_string()
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let config: Config = toml::from_str(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Builds the configuration from `STREAMS`/`STREAM_URL`, `DEFAULT_STREAM`, `PORT`,
+    /// `HISTORY_SIZE`, `HISTORY_MAX_AGE` and `HISTORY_FILE`.
+    pub fn from_env() -> Result<Self> {
+        let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
+        let defaults = HistoryConfig::default();
+        let config = Config {
+            listen: format!("0.0.0.0:{}", port),
+            default_stream: env::var("DEFAULT_STREAM").ok(),
+            cors_origins: Vec::new(),
+            history: HistoryConfig {
+                size: env_or("HISTORY_SIZE", defaults.size)?,
+                max_age: env_or("HISTORY_MAX_AGE", defaults.max_age)?,
+                file: env::var("HISTORY_FILE").ok(),
+            },
+            timing: TimingConfig::default(),
+            webhooks: WebhookSettings::default(),
+            streams: streams_from_env()?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.streams.is_empty() {
+            bail!("No streams configured");
+        }
+        for (index, stream) in self.streams.iter().enumerate() {
+            if stream.name.is_empty() || stream.url.is_empty() {
+                bail!("Stream #{} needs a name and a url", index + 1);
+            }
+            if stream.fallbacks.iter().any(|url| url.is_empty()) {
+                bail!("Stream '{}' has an empty fallback url", stream.name);
+            }
+            if stream.webhooks.iter().any(|hook| hook.url.is_empty()) {
+                bail!("Stream '{}' has a webhook without a url", stream.name);
+            }
+            if self.streams[..index].iter().any(|s| s.name == stream.name) {
+                bail!("Duplicate stream name '{}'", stream.name);
+            }
+        }
+        if let Some(name) = &self.default_stream {
+            if !self.streams.iter().any(|s| s.name == *name) {
+                bail!("Default stream '{}' is not a configured stream", name);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn default_stream(&self) -> &str {
+        self.default_stream.as_deref().unwrap_or(&self.streams[0].name)
+    }
+}
+
+/// Reads the stream list from the environment.
+///
+/// `STREAMS` takes a comma separated list of `name=url` pairs, e.g.
+/// `chiptune=https://cast.ruohki.services/chiptune.ogg,vapor=https://cast.ruohki.services/vapor.ogg`.
+/// Fallback urls follow the primary one separated by `|`.
+/// Without it, `STREAM_URL` is used as a single stream named `default`.
+fn streams_from_env() -> Result<Vec<StreamConfig>> {
+    let Ok(spec) = env::var("STREAMS") else {
+        let url = env::var("STREAM_URL")
+            .unwrap_or_else(|_| "https://cast.ruohki.services/chiptune.ogg".to_string());
+        return Ok(vec![StreamConfig {
+            name: "default".to_string(),
+            url,
+            fallbacks: Vec::new(),
+            webhooks: Vec::new(),
+        }]);
+    };
+
+    let mut streams = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, url) = entry
+            .split_once('=')
+            .with_context(|| format!("Invalid STREAMS entry '{}', expected name=url", entry))?;
+        let mut urls = url.split('|').map(|url| url.trim().to_string());
+        streams.push(StreamConfig {
+            name: name.trim().to_string(),
+            url: urls.next().unwrap_or_default(),
+            fallbacks: urls.collect(),
+            webhooks: Vec::new(),
+        });
+    }
+    Ok(streams)
+}
+
+/// Parses an optional environment variable, falling back to `default` when unset.
+fn env_or<T>(key: &str, default: T) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(key) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid {} '{}': {}", key, value, e)),
+        Err(_) => Ok(default),
+    }
+}
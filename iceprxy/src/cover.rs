@@ -0,0 +1,327 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use nom::{
+    bytes::complete::take,
+    number::complete::be_u32,
+    error::Error,
+    IResult,
+};
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hasher};
+
+// FLAC picture type of the front cover, preferred when a header carries several pictures
+const PICTURE_FRONT_COVER: u32 = 3;
+
+/// Cover art decoded from a comment header.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cover {
+    pub mime: String,
+    pub width: u32,
+    pub height: u32,
+    pub data: Bytes,
+    // Hex hash of the image data, used as ETag
+    pub hash: String,
+}
+
+/// What the metadata JSON says about the cover instead of carrying the image itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverInfo {
+    pub url: String,
+    pub hash: String,
+    pub mime: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Cover {
+    fn new(mime: String, width: u32, height: u32, data: Vec<u8>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&data);
+        let (sniffed_width, sniffed_height) = image_dimensions(&data).unwrap_or_default();
+        Self {
+            mime: if mime.is_empty() { sniff_mime(&data).to_string() } else { mime },
+            // Encoders may leave the dimensions of a picture block at zero
+            width: if width > 0 { width } else { sniffed_width },
+            height: if height > 0 { height } else { sniffed_height },
+            data: Bytes::from(data),
+            hash: format!("{:016x}", hasher.finish()),
+        }
+    }
+
+    pub fn info(&self, url: String) -> CoverInfo {
+        CoverInfo {
+            url,
+            hash: self.hash.clone(),
+            mime: self.mime.clone(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// Collects the cover related comments of a header and decodes the best picture.
+#[derive(Default)]
+pub struct CoverComments {
+    // Base64 FLAC picture blocks, one per METADATA_BLOCK_PICTURE
+    pictures: Vec<String>,
+    // Legacy COVERART with the raw base64 image and an optional COVERARTMIME
+    coverart: Option<String>,
+    coverart_mime: Option<String>,
+}
+
+impl CoverComments {
+    /// Takes the comment if it is cover art, returning false for every other key.
+    pub fn take(&mut self, key: &str, value: &str) -> bool {
+        match key.to_ascii_lowercase().as_str() {
+            "metadata_block_picture" => self.pictures.push(value.to_string()),
+            "coverart" => self.coverart = Some(value.to_string()),
+            "coverartmime" => self.coverart_mime = Some(value.to_string()),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn decode(self) -> Result<Option<Cover>> {
+        let mut pictures = Vec::new();
+        for picture in &self.pictures {
+            let block = decode_base64(picture).context("Invalid METADATA_BLOCK_PICTURE")?;
+            let (_, picture) = parse_picture_block(&block)
+                .ok()
+                .context("Truncated METADATA_BLOCK_PICTURE")?;
+            pictures.push(picture);
+        }
+        // Prefer the front cover, otherwise take the first picture
+        let index = pictures.iter().position(|p| p.kind == PICTURE_FRONT_COVER).unwrap_or(0);
+        if index < pictures.len() {
+            let picture = pictures.swap_remove(index);
+            return Ok(Some(Cover::new(picture.mime, picture.width, picture.height, picture.data)));
+        }
+
+        let Some(coverart) = self.coverart else {
+            return Ok(None);
+        };
+        let data = decode_base64(&coverart).context("Invalid COVERART")?;
+        Ok(Some(Cover::new(self.coverart_mime.unwrap_or_default(), 0, 0, data)))
+    }
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>> {
+    // Some taggers wrap long base64 values
+    let value: String = value.split_whitespace().collect();
+    Ok(STANDARD.decode(value)?)
+}
+
+struct Picture {
+    kind: u32,
+    mime: String,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// Parses a FLAC picture block: type, MIME type, description, dimensions, colour info and data.
+fn parse_picture_block(input: &[u8]) -> IResult<&[u8], Picture, Error<&[u8]>> {
+    let (input, kind) = be_u32(input)?;
+    let (input, mime_len) = be_u32(input)?;
+    let (input, mime) = take(mime_len)(input)?;
+    let (input, description_len) = be_u32(input)?;
+    let (input, _description) = take(description_len)(input)?;
+    let (input, width) = be_u32(input)?;
+    let (input, height) = be_u32(input)?;
+    // Colour depth and number of indexed colours
+    let (input, _) = take(8usize)(input)?;
+    let (input, data_len) = be_u32(input)?;
+    let (input, data) = take(data_len)(input)?;
+    Ok((input, Picture {
+        kind,
+        mime: String::from_utf8_lossy(mime).into_owned(),
+        width,
+        height,
+        data: data.to_vec(),
+    }))
+}
+
+fn sniff_mime(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Reads width and height from PNG, GIF and JPEG headers.
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be32 = |offset: usize| Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?));
+    let be16 = |offset: usize| Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?) as u32);
+    let le16 = |offset: usize| Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?) as u32);
+
+    match sniff_mime(data) {
+        // The IHDR chunk directly follows the signature
+        "image/png" => Some((be32(16)?, be32(20)?)),
+        "image/gif" => Some((le16(6)?, le16(8)?)),
+        "image/jpeg" => {
+            // Walk the segments up to the first start-of-frame marker
+            let mut offset = 2;
+            loop {
+                if *data.get(offset)? != 0xff {
+                    return None;
+                }
+                let marker = *data.get(offset + 1)?;
+                let is_frame = (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker);
+                if is_frame {
+                    return Some((be16(offset + 7)?, be16(offset + 5)?));
+                }
+                offset += 2 + be16(offset + 2)? as usize;
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data.extend([8, 6, 0, 0, 0]);
+        data
+    }
+
+    fn jpeg_segment(marker: u8, body: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xff, marker];
+        segment.extend((body.len() as u16 + 2).to_be_bytes());
+        segment.extend(body);
+        segment
+    }
+
+    fn jpeg(segments: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0xff, 0xd8];
+        data.extend(segments.concat());
+        data
+    }
+
+    // Precision, height, width and a single component
+    fn start_of_frame(width: u16, height: u16) -> Vec<u8> {
+        let mut body = vec![8];
+        body.extend(height.to_be_bytes());
+        body.extend(width.to_be_bytes());
+        body.extend([1, 1, 0x11, 0]);
+        body
+    }
+
+    fn picture_block(kind: u32, mime: &str, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+        let mut block = kind.to_be_bytes().to_vec();
+        block.extend((mime.len() as u32).to_be_bytes());
+        block.extend(mime.as_bytes());
+        block.extend(5u32.to_be_bytes());
+        block.extend(b"Cover");
+        block.extend(width.to_be_bytes());
+        block.extend(height.to_be_bytes());
+        block.extend([0; 8]);
+        block.extend((data.len() as u32).to_be_bytes());
+        block.extend(data);
+        block
+    }
+
+    fn decode(comments: &[(&str, String)]) -> Result<Option<Cover>> {
+        let mut cover = CoverComments::default();
+        for (key, value) in comments {
+            assert!(cover.take(key, value));
+        }
+        cover.decode()
+    }
+
+    #[test]
+    fn reads_image_dimensions() {
+        assert_eq!(image_dimensions(&png(640, 480)), Some((640, 480)));
+        assert_eq!(image_dimensions(b"GIF89a\x40\x01\xf0\x00\x00"), Some((320, 240)));
+        assert_eq!(image_dimensions(b"not an image"), None);
+    }
+
+    #[test]
+    fn walks_jpeg_segments_to_the_frame() {
+        let data = jpeg(&[
+            jpeg_segment(0xe0, b"JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00"),
+            // Huffman tables share the start-of-frame marker range
+            jpeg_segment(0xc4, &[0; 20]),
+            jpeg_segment(0xc2, &start_of_frame(1200, 800)),
+        ]);
+        assert_eq!(image_dimensions(&data), Some((1200, 800)));
+
+        let data = jpeg(&[jpeg_segment(0xc0, &start_of_frame(300, 300))]);
+        assert_eq!(image_dimensions(&data), Some((300, 300)));
+    }
+
+    #[test]
+    fn gives_up_on_broken_jpegs() {
+        let mut data = jpeg(&[jpeg_segment(0xe0, &[0; 14]), jpeg_segment(0xc0, &start_of_frame(300, 300))]);
+        data.truncate(data.len() - 6);
+        assert_eq!(image_dimensions(&data), None);
+        // A segment that does not start with a marker
+        assert_eq!(image_dimensions(b"\xff\xd8\xff\xe0\x00\x04\x00\x00\x00\xc0"), None);
+        assert_eq!(image_dimensions(b"\xff\xd8\xff"), None);
+    }
+
+    #[test]
+    fn parses_picture_blocks() {
+        let block = picture_block(PICTURE_FRONT_COVER, "image/png", 640, 480, b"image");
+        let (rest, picture) = parse_picture_block(&block).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(picture.kind, PICTURE_FRONT_COVER);
+        assert_eq!(picture.mime, "image/png");
+        assert_eq!((picture.width, picture.height), (640, 480));
+        assert_eq!(picture.data, b"image");
+
+        assert!(parse_picture_block(&block[..block.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn prefers_the_front_cover() {
+        let back = picture_block(4, "image/jpeg", 10, 10, b"back");
+        let front = picture_block(PICTURE_FRONT_COVER, "image/jpeg", 20, 20, b"front");
+        let cover = decode(&[
+            ("METADATA_BLOCK_PICTURE", STANDARD.encode(back)),
+            ("metadata_block_picture", STANDARD.encode(front)),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(cover.data.as_ref(), b"front");
+        assert_eq!((cover.width, cover.height), (20, 20));
+    }
+
+    #[test]
+    fn fills_in_missing_picture_details() {
+        let block = picture_block(0, "", 0, 0, &png(64, 32));
+        let cover = decode(&[("METADATA_BLOCK_PICTURE", STANDARD.encode(block))]).unwrap().unwrap();
+        assert_eq!(cover.mime, "image/png");
+        assert_eq!((cover.width, cover.height), (64, 32));
+    }
+
+    #[test]
+    fn decodes_legacy_coverart() {
+        let image = jpeg(&[jpeg_segment(0xc0, &start_of_frame(300, 200))]);
+        let encoded = STANDARD.encode(&image);
+        // Wrapped the way some taggers write long values
+        let (start, end) = encoded.split_at(8);
+        let cover = decode(&[("COVERART", format!("{}\n{}", start, end))]).unwrap().unwrap();
+        assert_eq!(cover.mime, "image/jpeg");
+        assert_eq!((cover.width, cover.height), (300, 200));
+        assert_eq!(cover.data.as_ref(), image.as_slice());
+    }
+
+    #[test]
+    fn rejects_truncated_picture_blocks() {
+        let block = picture_block(PICTURE_FRONT_COVER, "image/png", 1, 1, b"image");
+        assert!(decode(&[("METADATA_BLOCK_PICTURE", STANDARD.encode(&block[..10]))]).is_err());
+        assert!(decode(&[]).unwrap().is_none());
+    }
+}
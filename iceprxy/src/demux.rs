@@ -0,0 +1,291 @@
+use bytes::Bytes;
+use log::{debug, warn};
+use ogg::reading::{BasePacketReader, PageParser};
+use ogg::Packet;
+use std::collections::HashMap;
+
+const CAPTURE_PATTERN: &[u8] = b"OggS";
+const PAGE_HEADER_LEN: usize = 27;
+
+// Largest possible page: header + 255 lacing values + 255 * 255 bytes of body
+const MAX_PAGE_LEN: usize = PAGE_HEADER_LEN + 255 + 255 * 255;
+
+const FLAG_BOS: u8 = 0x02;
+const FLAG_EOS: u8 = 0x04;
+
+// Granule position of pages on which no packet ends
+const NO_GRANULE: u64 = u64::MAX;
+
+/// A complete Ogg page as it appeared on the wire, with the packets it completed.
+pub struct DemuxedPage {
+    pub bytes: Bytes,
+    pub serial: u32,
+    pub bos: bool,
+    // Codec specific position (samples for Vorbis, Opus and FLAC) at the end of the page
+    pub granule: Option<u64>,
+    pub packets: Vec<Packet>,
+}
+
+/// Incremental Ogg demuxer for network streams.
+///
+/// Raw bytes are pushed in whatever chunks the HTTP client hands out; complete
+/// pages are cut out of the buffer, checked against their CRC and sequence number
+/// and handed to the `ogg` crate, which reassembles packets per logical stream.
+pub struct OggDemuxer {
+    buffer: Vec<u8>,
+    reader: BasePacketReader,
+    // Last page sequence number per logical stream serial
+    sequences: HashMap<u32, u32>,
+}
+
+impl OggDemuxer {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            reader: Self::new_reader(),
+            sequences: HashMap::new(),
+        }
+    }
+
+    fn new_reader() -> BasePacketReader {
+        let mut reader = BasePacketReader::new();
+        // We usually join in the middle of a stream, so tell the reader to
+        // tolerate pages of streams it has not seen the start of.
+        reader.update_after_seek();
+        reader
+    }
+
+    fn reset_reader(&mut self) {
+        self.reader = Self::new_reader();
+        self.sequences.clear();
+    }
+
+    /// Feeds a chunk of raw stream bytes and returns all pages completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<DemuxedPage> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut pages = Vec::new();
+        let mut offset = 0;
+        loop {
+            // Resync on the capture pattern if we are not at a page start
+            match find_capture(&self.buffer[offset..]) {
+                Some(pos) => {
+                    if pos > 0 {
+                        debug!("Skipped {} bytes while looking for an Ogg page", pos);
+                    }
+                    offset += pos;
+                }
+                None => {
+                    // Keep a possible partial capture pattern at the end
+                    offset = self.buffer.len().saturating_sub(CAPTURE_PATTERN.len() - 1).max(offset);
+                    break;
+                }
+            }
+
+            match page_len(&self.buffer[offset..]) {
+                Some(len) if self.buffer.len() - offset >= len => {
+                    let page = Bytes::copy_from_slice(&self.buffer[offset..offset + len]);
+                    if let Some(page) = self.push_page(page) {
+                        pages.push(page);
+                        offset += len;
+                    } else {
+                        // Not a valid page, the capture pattern was part of the payload
+                        offset += 1;
+                    }
+                }
+                // Wait for the rest of the page
+                _ => break,
+            }
+        }
+
+        self.buffer.drain(..offset);
+        if self.buffer.len() > MAX_PAGE_LEN {
+            warn!("Ogg demuxer buffer overflow, dropping {} bytes", self.buffer.len());
+            self.buffer.clear();
+        }
+
+        pages
+    }
+
+    fn push_page(&mut self, page: Bytes) -> Option<DemuxedPage> {
+        let mut header = [0u8; PAGE_HEADER_LEN];
+        header.copy_from_slice(&page[..PAGE_HEADER_LEN]);
+        let flags = header[5];
+        let granule = u64::from_le_bytes(header[6..14].try_into().unwrap());
+        let serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+        let sequence = u32::from_le_bytes(header[18..22].try_into().unwrap());
+
+        let Ok((mut parser, segment_count)) = PageParser::new(header) else {
+            return None;
+        };
+        let segments = &page[PAGE_HEADER_LEN..PAGE_HEADER_LEN + segment_count];
+        let body_len = parser.parse_segments(segments.to_vec());
+        let body = &page[PAGE_HEADER_LEN + segment_count..];
+        debug_assert_eq!(body.len(), body_len);
+        let Ok(ogg_page) = parser.parse_packet_data(body.to_vec()) else {
+            return None;
+        };
+
+        let is_bos = flags & FLAG_BOS != 0;
+        let is_eos = flags & FLAG_EOS != 0;
+        let mut demuxed = DemuxedPage {
+            bytes: page.clone(),
+            serial,
+            bos: is_bos,
+            granule: (granule != NO_GRANULE).then_some(granule),
+            packets: Vec::new(),
+        };
+        match self.sequences.get(&serial) {
+            Some(_) if is_bos => {
+                debug!("Logical stream {:08x} restarted, resetting demuxer", serial);
+                self.reset_reader();
+            }
+            Some(&last) if sequence != last.wrapping_add(1) => {
+                warn!(
+                    "Page sequence gap in logical stream {:08x} ({} -> {}), dropping partial packets",
+                    serial, last, sequence
+                );
+                self.reset_reader();
+            }
+            _ => {}
+        }
+        self.sequences.insert(serial, sequence);
+
+        if let Err(e) = self.reader.push_page(ogg_page) {
+            warn!("Failed to push Ogg page of logical stream {:08x}: {}", serial, e);
+            self.reset_reader();
+            return Some(demuxed);
+        }
+        while let Some(packet) = self.reader.read_packet() {
+            demuxed.packets.push(packet);
+        }
+
+        if is_eos {
+            self.sequences.remove(&serial);
+            // Chained streams get a fresh serial per track, so drop the state
+            // of finished streams once nothing else is in flight.
+            if self.sequences.is_empty() {
+                self.reader = Self::new_reader();
+            }
+        }
+
+        Some(demuxed)
+    }
+}
+
+fn find_capture(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(CAPTURE_PATTERN.len())
+        .position(|window| window == CAPTURE_PATTERN)
+}
+
+/// Total length of the page at the start of `buffer`, if enough of it is buffered to tell.
+fn page_len(buffer: &[u8]) -> Option<usize> {
+    if buffer.len() < PAGE_HEADER_LEN {
+        return None;
+    }
+    let segment_count = buffer[PAGE_HEADER_LEN - 1] as usize;
+    let segments = buffer.get(PAGE_HEADER_LEN..PAGE_HEADER_LEN + segment_count)?;
+    let body_len: usize = segments.iter().map(|&s| s as usize).sum();
+    Some(PAGE_HEADER_LEN + segment_count + body_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERIAL: u32 = 0x1234_5678;
+    const FLAG_CONTINUED: u8 = 0x01;
+
+    fn crc(data: &[u8]) -> u32 {
+        let mut crc = 0u32;
+        for &byte in data {
+            crc ^= (byte as u32) << 24;
+            for _ in 0..8 {
+                crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 };
+            }
+        }
+        crc
+    }
+
+    /// Builds a page with a valid checksum from its lacing values and body.
+    fn page(sequence: u32, flags: u8, granule: u64, lacing: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.extend([0, flags]);
+        page.extend(granule.to_le_bytes());
+        page.extend(SERIAL.to_le_bytes());
+        page.extend(sequence.to_le_bytes());
+        page.extend([0; 4]);
+        page.push(lacing.len() as u8);
+        page.extend(lacing);
+        page.extend(body);
+        let crc = crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    /// A page carrying a single complete packet.
+    fn packet_page(sequence: u32, packet: &[u8]) -> Vec<u8> {
+        let mut lacing = vec![255; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+        page(sequence, 0, sequence as u64, &lacing, packet)
+    }
+
+    #[test]
+    fn reassembles_a_packet_spanning_two_pages() {
+        let packet: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let mut demuxer = OggDemuxer::new();
+
+        let first = demuxer.push(&page(0, 0, NO_GRANULE, &[255], &packet[..255]));
+        assert_eq!(first.len(), 1);
+        assert!(first[0].packets.is_empty());
+        assert_eq!(first[0].granule, None);
+
+        let second = demuxer.push(&page(1, FLAG_CONTINUED, 300, &[45], &packet[255..]));
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].granule, Some(300));
+        assert_eq!(second[0].packets.len(), 1);
+        assert_eq!(second[0].packets[0].data, packet);
+    }
+
+    #[test]
+    fn waits_for_a_page_split_inside_its_header() {
+        let page = packet_page(0, b"comment");
+        let mut demuxer = OggDemuxer::new();
+
+        assert!(demuxer.push(&page[..10]).is_empty());
+        let pages = demuxer.push(&page[10..]);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].serial, SERIAL);
+        assert_eq!(pages[0].bytes.as_ref(), page.as_slice());
+        assert_eq!(pages[0].packets[0].data, b"comment");
+    }
+
+    #[test]
+    fn skips_garbage_containing_the_capture_pattern() {
+        // A fake page header without segments whose checksum does not match
+        let mut chunk = b"noise OggS".to_vec();
+        chunk.extend([0; 23]);
+        chunk.extend(b"more noise");
+        chunk.extend(packet_page(0, b"comment"));
+
+        let pages = OggDemuxer::new().push(&chunk);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].packets[0].data, b"comment");
+    }
+
+    #[test]
+    fn drops_the_partial_packet_on_a_sequence_gap() {
+        let mut demuxer = OggDemuxer::new();
+        demuxer.push(&page(0, 0, NO_GRANULE, &[255], &[1; 255]));
+
+        // Page 1 with the end of the packet got lost
+        let after_gap = demuxer.push(&page(2, FLAG_CONTINUED, NO_GRANULE, &[45], &[2; 45]));
+        assert_eq!(after_gap.len(), 1);
+        assert!(after_gap[0].packets.is_empty());
+
+        let next = demuxer.push(&packet_page(3, b"next"));
+        assert_eq!(next[0].packets.len(), 1);
+        assert_eq!(next[0].packets[0].data, b"next");
+    }
+}
@@ -0,0 +1,44 @@
+use crate::StreamMetadata;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Field level difference between two consecutive metadata snapshots of a stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MetadataDiff {
+    // Fields that were not set before, with their new value
+    pub added: BTreeMap<String, String>,
+    pub changed: BTreeMap<String, FieldChange>,
+    // Fields that are no longer set, with their last value
+    pub removed: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub from: String,
+    pub to: String,
+}
+
+impl MetadataDiff {
+    /// Compares the fields of `previous` (nothing if this is the first snapshot) with `next`.
+    pub fn between(previous: Option<&StreamMetadata>, next: &StreamMetadata) -> Self {
+        let mut old = previous.map(StreamMetadata::fields).unwrap_or_default();
+        let mut diff = Self::default();
+        for (key, value) in next.fields() {
+            match old.remove(&key) {
+                None => {
+                    diff.added.insert(key, value);
+                }
+                Some(from) if from != value => {
+                    diff.changed.insert(key, FieldChange { from, to: value });
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = old;
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
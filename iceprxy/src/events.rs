@@ -0,0 +1,150 @@
+use crate::{unix_millis, StreamEvent};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+// Recent events kept per stream for clients resuming with `Last-Event-ID`
+const REPLAY_CAPACITY: usize = 256;
+
+/// A stream event together with its id.
+#[derive(Debug, Clone)]
+pub struct SequencedEvent {
+    pub id: u64,
+    pub event: StreamEvent,
+}
+
+/// Numbers the events of a stream and keeps the recent ones for replay.
+///
+/// Ids increase monotonically and follow the wall clock in milliseconds where
+/// they can, so they keep growing across restarts and an id the buffer no
+/// longer covers still tells which history entries a client has missed.
+pub struct EventLog {
+    tx: broadcast::Sender<SequencedEvent>,
+    state: Mutex<LogState>,
+}
+
+struct LogState {
+    last_id: u64,
+    // Events with an id up to this one are no longer buffered
+    floor: u64,
+    recent: VecDeque<SequencedEvent>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(100);
+        let now = unix_millis();
+        Self {
+            tx,
+            state: Mutex::new(LogState {
+                last_id: now,
+                floor: now,
+                recent: VecDeque::with_capacity(REPLAY_CAPACITY),
+            }),
+        }
+    }
+
+    /// Assigns the next id to `event` and broadcasts it, returning the number of subscribers reached.
+    pub fn send(&self, mut event: StreamEvent) -> usize {
+        // Subscribers only get the JSON, so do not keep past cover images around.
+        // `/cover` serves the current one from the stream's metadata.
+        if let StreamEvent::Metadata(metadata) = &mut event {
+            metadata.picture = None;
+        }
+        let mut state = self.state.lock().unwrap();
+        let id = (state.last_id + 1).max(unix_millis());
+        state.last_id = id;
+        let event = SequencedEvent { id, event };
+        if state.recent.len() == REPLAY_CAPACITY {
+            if let Some(evicted) = state.recent.pop_front() {
+                state.floor = evicted.id;
+            }
+        }
+        state.recent.push_back(event.clone());
+        // Sent under the lock so the buffer and the channel agree on the order.
+        // Without subscribers the event is only kept for replay.
+        self.tx.send(event).unwrap_or(0)
+    }
+
+    /// Subscribes to new events, returning the id of the latest event sent before.
+    pub fn subscribe(&self) -> (broadcast::Receiver<SequencedEvent>, u64) {
+        let state = self.state.lock().unwrap();
+        (self.tx.subscribe(), state.last_id)
+    }
+
+    /// Subscribes to new events and returns the buffered events after `after`.
+    ///
+    /// Returns `None` if some of the events the client missed are no longer
+    /// buffered, or the id is not one of ours.
+    pub fn resume(&self, after: u64) -> (broadcast::Receiver<SequencedEvent>, u64, Option<Vec<SequencedEvent>>) {
+        let state = self.state.lock().unwrap();
+        let missed = (state.floor..=state.last_id).contains(&after).then(|| {
+            state.recent.iter().filter(|event| event.id > after).cloned().collect()
+        });
+        (self.tx.subscribe(), state.last_id, missed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{HealthState, StreamStatus};
+
+    fn send(log: &EventLog, count: usize) -> Vec<u64> {
+        (0..count)
+            .map(|_| {
+                log.send(StreamEvent::Status(StreamStatus::new(HealthState::Live)));
+                log.state.lock().unwrap().last_id
+            })
+            .collect()
+    }
+
+    fn replayed(log: &EventLog, after: u64) -> Option<Vec<u64>> {
+        let (_, _, missed) = log.resume(after);
+        missed.map(|events| events.iter().map(|event| event.id).collect())
+    }
+
+    #[test]
+    fn ids_increase() {
+        let log = EventLog::new();
+        let ids = send(&log, 10);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn resumes_at_the_floor() {
+        let log = EventLog::new();
+        let floor = log.state.lock().unwrap().floor;
+        let ids = send(&log, 3);
+        assert_eq!(replayed(&log, floor), Some(ids.clone()));
+        assert_eq!(replayed(&log, ids[0]), Some(ids[1..].to_vec()));
+    }
+
+    #[test]
+    fn does_not_resume_below_the_floor() {
+        let log = EventLog::new();
+        let floor = log.state.lock().unwrap().floor;
+        send(&log, 3);
+        assert_eq!(replayed(&log, floor - 1), None);
+        assert_eq!(replayed(&log, 0), None);
+    }
+
+    #[test]
+    fn eviction_moves_the_floor() {
+        let log = EventLog::new();
+        let start = log.state.lock().unwrap().floor;
+        let ids = send(&log, REPLAY_CAPACITY + 1);
+        assert_eq!(log.state.lock().unwrap().floor, ids[0]);
+        assert_eq!(replayed(&log, start), None);
+        assert_eq!(replayed(&log, ids[0]), Some(ids[1..].to_vec()));
+    }
+
+    #[test]
+    fn does_not_resume_past_the_latest_id() {
+        let log = EventLog::new();
+        let ids = send(&log, 2);
+        assert_eq!(replayed(&log, ids[1]), Some(Vec::new()));
+        assert_eq!(replayed(&log, ids[1] + 1), None);
+        assert_eq!(replayed(&log, u64::MAX), None);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::config::TimingConfig;
+use crate::unix_millis;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    // Connecting to the upstream, no data received yet
+    Connecting,
+    // Receiving data
+    Live,
+    // Connected, but no data arrived for a while
+    Stalled,
+    // Disconnected, waiting to retry
+    Down,
+}
+
+/// Upstream health of a stream, as exposed by the API and pushed over `/live`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamStatus {
+    pub state: HealthState,
+    // When the stream entered this state
+    pub since: u64,
+    // Consecutive failed connection attempts
+    pub failures: u32,
+    // Upstream url in use, which differs from the configured one after a failover
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_in_ms: Option<u64>,
+}
+
+impl StreamStatus {
+    pub fn new(state: HealthState) -> Self {
+        Self {
+            state,
+            since: unix_millis(),
+            failures: 0,
+            url: None,
+            error: None,
+            retry_in_ms: None,
+        }
+    }
+}
+
+/// Reported when the upstream stops sending data without closing the connection.
+#[derive(Debug, Clone, Serialize)]
+pub struct StallEvent {
+    pub at: u64,
+    // How long no data has arrived
+    pub idle_ms: u64,
+    // Whether the connection is dropped and retried because of it
+    pub reconnecting: bool,
+}
+
+impl StallEvent {
+    pub fn new(idle: Duration, reconnecting: bool) -> Self {
+        Self {
+            at: unix_millis(),
+            idle_ms: idle.as_millis() as u64,
+            reconnecting,
+        }
+    }
+}
+
+/// Exponential reconnect backoff with jitter.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    jitter: f64,
+    pub failures: u32,
+    random: RandomState,
+}
+
+impl Backoff {
+    pub fn new(timing: &TimingConfig) -> Self {
+        Self {
+            initial: timing.retry_delay(),
+            max: timing.retry_max_delay(),
+            multiplier: timing.retry_multiplier.max(1.0),
+            jitter: timing.retry_jitter.clamp(0.0, 1.0),
+            failures: 0,
+            random: RandomState::new(),
+        }
+    }
+
+    /// Resets the delay after the upstream was live again.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Returns the delay before the next attempt and advances the backoff.
+    pub fn next_delay(&mut self) -> Duration {
+        let exponent = self.failures.min(32) as i32;
+        self.failures = self.failures.saturating_add(1);
+
+        let base = (self.initial.as_secs_f64() * self.multiplier.powi(exponent)).min(self.max.as_secs_f64());
+        // Spread reconnects by +-jitter so several streams do not retry in lockstep
+        let spread = 1.0 + self.jitter * (2.0 * self.random_unit() - 1.0);
+        Duration::from_secs_f64((base * spread).min(self.max.as_secs_f64()).max(0.0))
+    }
+
+    fn random_unit(&self) -> f64 {
+        // RandomState is seeded randomly per instance; hashing the attempt gives a cheap random value
+        let bits = self.random.hash_one(self.failures);
+        (bits >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{unix_millis, StreamMetadata};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub metadata: StreamMetadata,
+    pub started_at: u64,
+    // None while the track is still playing
+    pub ended_at: Option<u64>,
+}
+
+/// Ring buffer of recently played tracks, newest at the back.
+pub struct TrackHistory {
+    entries: VecDeque<HistoryEntry>,
+    max_entries: usize,
+    max_age: Duration,
+}
+
+impl TrackHistory {
+    pub fn new(max_entries: usize, max_age: Duration) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(max_entries.min(1024)),
+            max_entries,
+            max_age,
+        }
+    }
+
+    pub fn set_limits(&mut self, max_entries: usize, max_age: Duration) {
+        self.max_entries = max_entries;
+        self.max_age = max_age;
+        self.prune(unix_millis());
+    }
+
+    /// Records the start of a new track, ending the one that was playing before.
+    pub fn record(&mut self, mut metadata: StreamMetadata) -> HistoryEntry {
+        let now = unix_millis();
+        // Only the current track's cover is served, so do not keep the images around
+        metadata.picture = None;
+        if let Some(current) = self.entries.back_mut() {
+            current.ended_at.get_or_insert(now);
+        }
+        let entry = HistoryEntry {
+            metadata,
+            started_at: now,
+            ended_at: None,
+        };
+        self.entries.push_back(entry.clone());
+        self.prune(now);
+        entry
+    }
+
+    /// Replaces the metadata of the track that is currently playing, e.g. when its tags changed.
+    pub fn update_current(&mut self, mut metadata: StreamMetadata) {
+        metadata.picture = None;
+        if let Some(current) = self.entries.back_mut().filter(|entry| entry.ended_at.is_none()) {
+            current.metadata = metadata;
+        }
+    }
+
+    /// Replays persisted entries, oldest first, into an empty history.
+    pub fn restore(&mut self, entries: Vec<HistoryEntry>) {
+        self.entries.extend(entries);
+        self.prune(unix_millis());
+    }
+
+    /// The track that is currently playing, if any.
+    pub fn current(&self) -> Option<&HistoryEntry> {
+        self.entries.back().filter(|entry| entry.ended_at.is_none())
+    }
+
+    /// Returns up to `limit` entries that started at or after `since`, newest first.
+    pub fn query(&mut self, limit: Option<usize>, since: Option<u64>) -> Vec<HistoryEntry> {
+        self.prune(unix_millis());
+        self.entries
+            .iter()
+            .rev()
+            .take_while(|entry| since.is_none_or(|since| entry.started_at >= since))
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    fn prune(&mut self, now: u64) {
+        while self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+        }
+        let max_age = self.max_age.as_millis() as u64;
+        while self
+            .entries
+            .front()
+            .and_then(|entry| entry.ended_at)
+            .is_some_and(|ended_at| now.saturating_sub(ended_at) > max_age)
+        {
+            self.entries.pop_front();
+        }
+    }
+}
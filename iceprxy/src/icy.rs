@@ -0,0 +1,184 @@
+use log::debug;
+
+/// Strips ICY (Shoutcast) in-band metadata blocks out of an MP3/AAC byte stream.
+///
+/// With `Icy-MetaData: 1` the server inserts a metadata block after every
+/// `icy-metaint` bytes of audio: one length byte (in units of 16 bytes) followed
+/// by a NUL padded `StreamTitle='...';` string.
+pub struct IcyReader {
+    metaint: usize,
+    state: IcyState,
+}
+
+enum IcyState {
+    // Audio bytes left until the next metadata block
+    Audio(usize),
+    // Waiting for the length byte of a metadata block
+    Length,
+    // Collecting a metadata block of the given length
+    Metadata(usize, Vec<u8>),
+}
+
+impl IcyReader {
+    pub fn new(metaint: usize) -> Self {
+        Self {
+            metaint,
+            state: IcyState::Audio(metaint),
+        }
+    }
+
+    /// Feeds a chunk of raw stream bytes and splits it into audio and completed metadata blocks.
+    pub fn push(&mut self, mut chunk: &[u8]) -> IcyChunk {
+        let mut audio = Vec::with_capacity(chunk.len());
+        let mut blocks = Vec::new();
+        while !chunk.is_empty() {
+            match &mut self.state {
+                IcyState::Audio(remaining) => {
+                    let n = (*remaining).min(chunk.len());
+                    audio.extend_from_slice(&chunk[..n]);
+                    chunk = &chunk[n..];
+                    *remaining -= n;
+                    if *remaining == 0 {
+                        self.state = IcyState::Length;
+                    }
+                }
+                IcyState::Length => {
+                    let len = chunk[0] as usize * 16;
+                    chunk = &chunk[1..];
+                    self.state = if len == 0 {
+                        IcyState::Audio(self.metaint)
+                    } else {
+                        IcyState::Metadata(len, Vec::with_capacity(len))
+                    };
+                }
+                IcyState::Metadata(len, block) => {
+                    let n = (*len - block.len()).min(chunk.len());
+                    block.extend_from_slice(&chunk[..n]);
+                    chunk = &chunk[n..];
+                    if block.len() == *len {
+                        let text = decode_icy_text(block);
+                        debug!("ICY metadata block: {}", text);
+                        blocks.push(text);
+                        self.state = IcyState::Audio(self.metaint);
+                    }
+                }
+            }
+        }
+        IcyChunk {
+            audio,
+            metadata: blocks,
+        }
+    }
+}
+
+pub struct IcyChunk {
+    pub audio: Vec<u8>,
+    pub metadata: Vec<String>,
+}
+
+/// ICY metadata has no declared charset; most servers send UTF-8, older ones Latin-1.
+fn decode_icy_text(block: &[u8]) -> String {
+    let block = block
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(&block[..0], |end| &block[..=end]);
+    match std::str::from_utf8(block) {
+        Ok(text) => text.to_string(),
+        Err(_) => block.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// Splits a metadata block like `StreamTitle='Artist - Title';StreamUrl='';` into key/value pairs.
+///
+/// Values are terminated by `';` rather than the next quote, so titles containing
+/// apostrophes survive.
+pub fn parse_icy_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let mut rest = text;
+    while let Some((key, value)) = rest.split_once("='") {
+        let (value, next) = match value.find("';") {
+            Some(end) => (&value[..end], &value[end + 2..]),
+            None => (value.strip_suffix('\'').unwrap_or(value), ""),
+        };
+        fields.push((key.trim().to_string(), value.to_string()));
+        rest = next;
+    }
+    fields
+}
+
+/// Splits a `StreamTitle` into artist and title on the conventional " - " separator.
+pub fn split_stream_title(stream_title: &str) -> (Option<String>, String) {
+    match stream_title.split_once(" - ") {
+        Some((artist, title)) if !artist.trim().is_empty() => {
+            (Some(artist.trim().to_string()), title.trim().to_string())
+        }
+        _ => (None, stream_title.trim().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes `text` as a metadata block: length byte followed by the NUL padded text.
+    fn block(text: &str) -> Vec<u8> {
+        let len = text.len().div_ceil(16);
+        let mut block = vec![len as u8];
+        block.extend(text.as_bytes());
+        block.resize(1 + len * 16, 0);
+        block
+    }
+
+    fn stream(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn reassembles_a_block_split_across_chunks() {
+        let data = stream(&[&[1; 8], &block("StreamTitle='Alpha - First Song';"), &[2; 8]]);
+        let mut reader = IcyReader::new(8);
+
+        let first = reader.push(&data[..15]);
+        assert_eq!(first.audio, [1; 8]);
+        assert!(first.metadata.is_empty());
+
+        let second = reader.push(&data[15..]);
+        assert_eq!(second.audio, [2; 8]);
+        assert_eq!(second.metadata, ["StreamTitle='Alpha - First Song';"]);
+    }
+
+    #[test]
+    fn skips_zero_length_blocks() {
+        let data = stream(&[&[1; 4], &[0], &[2; 4], &[0], &[3; 2]]);
+        let chunk = IcyReader::new(4).push(&data);
+        assert_eq!(chunk.audio, [[1; 4].as_slice(), &[2; 4], &[3; 2]].concat());
+        assert!(chunk.metadata.is_empty());
+    }
+
+    #[test]
+    fn handles_metaint_on_a_chunk_boundary() {
+        let mut reader = IcyReader::new(4);
+        let first = reader.push(&[1; 4]);
+        assert_eq!(first.audio, [1; 4]);
+
+        let second = reader.push(&stream(&[&block("StreamTitle='Song';"), &[2; 4]]));
+        assert_eq!(second.audio, [2; 4]);
+        assert_eq!(second.metadata, ["StreamTitle='Song';"]);
+    }
+
+    #[test]
+    fn keeps_apostrophes_in_titles() {
+        let fields = parse_icy_fields("StreamTitle='Guns N' Roses - Sweet Child O' Mine';StreamUrl='';");
+        assert_eq!(
+            fields,
+            [
+                ("StreamTitle".to_string(), "Guns N' Roses - Sweet Child O' Mine".to_string()),
+                ("StreamUrl".to_string(), String::new()),
+            ]
+        );
+        assert_eq!(
+            split_stream_title(&fields[0].1),
+            (Some("Guns N' Roses".to_string()), "Sweet Child O' Mine".to_string())
+        );
+    }
+}
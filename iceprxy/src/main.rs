@@ -1,29 +1,78 @@
-use anyhow::{Context, Result};
+mod codec;
+mod config;
+mod cover;
+mod demux;
+mod diff;
+mod events;
+mod health;
+mod history;
+mod icy;
+mod metrics;
+mod relay;
+mod stats;
+mod store;
+mod supervisor;
+mod track;
+mod webhook;
+mod ws;
+
+use anyhow::{anyhow, bail, Context, Result};
 use axum::{
     routing::get,
     Router,
-    extract::State,
-    http::{StatusCode, Method},
+    extract::{Path, Query, State},
+    body::Body,
+    http::{header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH}, HeaderMap, StatusCode, Method},
     response::{IntoResponse, Sse},
     Json,
 };
-use futures::{stream::{self, Stream}, StreamExt};
-use log::{error, info};
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use log::{debug, error, info, warn};
 use nom::{
-    bytes::complete::{tag, take},
+    bytes::complete::take,
     number::complete::le_u32,
     error::Error,
     IResult,
 };
 use serde::{Serialize, Deserialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
-use tokio::sync::{RwLock, broadcast};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{RwLock, broadcast::{self, error::RecvError}};
+use tokio::time::Instant;
 use std::time::{SystemTime, UNIX_EPOCH, Duration};
 use std::env;
-use tower_http::cors::{CorsLayer, Any};
+use std::path::PathBuf;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use std::convert::Infallible;
 use axum::response::sse::Event;
+use codec::{AudioInfo, StreamHeaders};
+use config::{Config, StreamConfig, TimingConfig};
+use cover::{Cover, CoverComments, CoverInfo};
+use demux::OggDemuxer;
+use diff::MetadataDiff;
+use events::{EventLog, SequencedEvent};
+use health::{HealthState, StallEvent, StreamStatus};
+use history::TrackHistory;
+use metrics::{StreamMetrics, StreamSample};
+use relay::AudioRelay;
+use stats::{ListenerGuard, ListenerKind, ListenerSnapshot, ListenerStats};
+use store::{HistoryStore, JsonlStore};
+use supervisor::{StreamSettings, Supervisor};
+use webhook::WebhookNotifier;
+use track::{parse_duration, EndingSoon, NextTrack, TrackClock, TrackId};
+use icy::{parse_icy_fields, split_stream_title, IcyReader};
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+// Joins repeated comment values, e.g. several ARTIST entries of a collaboration
+const VALUE_SEPARATOR: &str = "; ";
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct StreamMetadata {
@@ -31,8 +80,54 @@ struct StreamMetadata {
     artist: Option<String>,
     album: Option<String>,
     genre: Option<String>,
+    // The rest of the standard Vorbis comment fields, only present when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tracknumber: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    composer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    performer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    organization: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    copyright: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    isrc: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contact: Option<String>,
+    // From LENGTH or DURATION comments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    // From NEXT_TITLE and NEXT_ARTIST comments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next: Option<NextTrack>,
     #[serde(flatten)]
     other: HashMap<String, String>,
+    // Every value per key in comment order; the fields above join repeated values
+    #[serde(default)]
+    values: BTreeMap<String, Vec<String>>,
+    // Codec details of the logical stream the comment header arrived on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audio: Option<AudioInfo>,
+    // Replaces embedded cover art, which is served from /cover instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cover: Option<CoverInfo>,
+    #[serde(skip)]
+    picture: Option<Arc<Cover>>,
+    // Encoder that wrote the comment header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vendor: Option<String>,
+    // Ogg logical stream the comment header arrived on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    serial: Option<u32>,
     last_update: u64,
 }
 
@@ -43,14 +138,71 @@ impl StreamMetadata {
             artist: None,
             album: None,
             genre: None,
+            tracknumber: None,
+            date: None,
+            composer: None,
+            performer: None,
+            organization: None,
+            copyright: None,
+            license: None,
+            isrc: None,
+            description: None,
+            location: None,
+            contact: None,
+            duration_ms: None,
+            next: None,
             other: HashMap::new(),
-            last_update: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64,
+            values: BTreeMap::new(),
+            audio: None,
+            cover: None,
+            picture: None,
+            vendor: None,
+            serial: None,
+            last_update: unix_millis(),
         }
     }
 
+    /// The optional typed fields by canonical key.
+    fn typed_fields(&self) -> [(&'static str, &Option<String>); 14] {
+        [
+            ("artist", &self.artist),
+            ("album", &self.album),
+            ("genre", &self.genre),
+            ("tracknumber", &self.tracknumber),
+            ("date", &self.date),
+            ("composer", &self.composer),
+            ("performer", &self.performer),
+            ("organization", &self.organization),
+            ("copyright", &self.copyright),
+            ("license", &self.license),
+            ("isrc", &self.isrc),
+            ("description", &self.description),
+            ("location", &self.location),
+            ("contact", &self.contact),
+        ]
+    }
+
+    fn typed_field_mut(&mut self, key: &str) -> Option<&mut Option<String>> {
+        let field = match key {
+            "artist" => &mut self.artist,
+            "album" => &mut self.album,
+            "genre" => &mut self.genre,
+            "tracknumber" => &mut self.tracknumber,
+            "date" => &mut self.date,
+            "composer" => &mut self.composer,
+            "performer" => &mut self.performer,
+            "organization" => &mut self.organization,
+            "copyright" => &mut self.copyright,
+            "license" => &mut self.license,
+            "isrc" => &mut self.isrc,
+            "description" => &mut self.description,
+            "location" => &mut self.location,
+            "contact" => &mut self.contact,
+            _ => return None,
+        };
+        Some(field)
+    }
+
     fn display(&self) -> String {
         let mut parts = Vec::new();
         if let Some(artist) = &self.artist {
@@ -63,86 +215,87 @@ impl StreamMetadata {
         if let Some(genre) = &self.genre {
             parts.push(format!("Genre: {}", genre.trim()));
         }
-        // Add any other metadata that might contain special characters
-        for (key, value) in &self.other {
-            if !["artist", "title", "album", "genre"].contains(&key.to_lowercase().as_str()) {
-                parts.push(format!("{}: {}", key.trim(), value.trim()));
+        // Add any other metadata that might contain special characters,
+        // skipping artist, album and genre which are listed above
+        for (key, value) in self.typed_fields().into_iter().skip(3) {
+            if let Some(value) = value {
+                parts.push(format!("{}: {}", key, value.trim()));
             }
         }
+        for (key, value) in &self.other {
+            parts.push(format!("{}: {}", key, value.trim()));
+        }
         parts.join(" | ")
     }
 
-    fn update_from_comment(&mut self, key: &str, value: &str) -> bool {
-        let mut updated = false;
-        match key.to_lowercase().as_str() {
-            "artist" => {
-                if self.artist.as_deref() != Some(value) {
-                    self.artist = Some(value.to_string());
-                    updated = true;
-                }
-            }
-            "title" => {
-                if self.title != value {
-                    self.title = value.to_string();
-                    updated = true;
-                }
-            }
-            "album" => {
-                if self.album.as_deref() != Some(value) {
-                    self.album = Some(value.to_string());
-                    updated = true;
-                }
-            }
-            "genre" => {
-                if self.genre.as_deref() != Some(value) {
-                    self.genre = Some(value.to_string());
-                    updated = true;
-                }
-            }
-            _ => {
-                if !self.other.contains_key(key) || self.other.get(key).map(|v| v.as_str()) != Some(value) {
-                    self.other.insert(key.to_string(), value.to_string());
-                    updated = true;
-                }
+    /// All set fields by key, as compared between snapshots.
+    fn fields(&self) -> BTreeMap<String, String> {
+        let mut fields: BTreeMap<String, String> = self.other.clone().into_iter().collect();
+        fields.insert("title".to_string(), self.title.clone());
+        for (key, value) in self.typed_fields() {
+            if let Some(value) = value {
+                fields.insert(key.to_string(), value.clone());
             }
         }
-        if updated {
-            self.last_update = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64;
+        if let Some(cover) = &self.cover {
+            fields.insert("cover".to_string(), cover.hash.clone());
+        }
+        if let Some(duration_ms) = self.duration_ms {
+            fields.insert("duration_ms".to_string(), duration_ms.to_string());
         }
-        updated
+        if let Some(next) = &self.next {
+            let next = match &next.artist {
+                Some(artist) => format!("{} - {}", artist, next.title),
+                None => next.title.clone(),
+            };
+            fields.insert("next".to_string(), next);
+        }
+        fields
     }
 
-    fn is_complete(&self) -> bool {
-        self.title != "Unknown" || self.artist.is_some()
+    /// Adds a comment under its canonical (lowercase) key. Repeated keys keep
+    /// all their values in order and the display field joins them.
+    fn add_comment(&mut self, key: &str, value: &str) {
+        let key = key.to_ascii_lowercase();
+        let values = self.values.entry(key.clone()).or_default();
+        values.push(value.to_string());
+        let display = values.join(VALUE_SEPARATOR);
+        match key.as_str() {
+            "title" => self.title = display,
+            "length" | "duration" => self.duration_ms = parse_duration(value),
+            // Combined by `resolve_next` once all comments are in
+            "next_title" | "nexttitle" | "next" | "next_artist" => {}
+            _ => match self.typed_field_mut(&key) {
+                Some(field) => *field = Some(display),
+                None => {
+                    self.other.insert(key, display);
+                }
+            },
+        }
+        self.last_update = unix_millis();
     }
-}
 
-fn find_vorbis_comment_start(buffer: &[u8]) -> Vec<usize> {
-    let mut positions = Vec::new();
-    
-    // Look for both initial metadata (type 1) and metadata updates (type 3)
-    let patterns = [
-        // Initial metadata pattern (type 1)
-        &[0x01, 0x76, 0x6F, 0x72, 0x62, 0x69, 0x73][..],
-        // Metadata update pattern (type 3)
-        &[0x03, 0x76, 0x6F, 0x72, 0x62, 0x69, 0x73][..],
-    ];
-
-    for pattern in patterns.iter() {
-        let mut start = 0;
-        while let Some(pos) = buffer[start..].windows(pattern.len())
-            .position(|window| window == *pattern)
-        {
-            let absolute_pos = start + pos + 1; // Skip the packet type byte
-            positions.push(absolute_pos);
-            start = absolute_pos;
-        }
+    /// Sets the upcoming track from the `NEXT_*` comments, in whatever order they came.
+    fn resolve_next(&mut self) {
+        let first = |keys: &[&str]| keys.iter().find_map(|key| self.values.get(*key)?.first().cloned());
+        let artist = first(&["next_artist"]);
+        self.next = first(&["next_title", "nexttitle", "next"]).map(|title| match artist {
+            Some(artist) => NextTrack { artist: Some(artist), title },
+            // Without NEXT_ARTIST the title may carry "Artist - Title" like an ICY StreamTitle
+            None => {
+                let (artist, title) = split_stream_title(&title);
+                NextTrack { artist, title }
+            }
+        });
     }
 
-    positions
+    fn track_id(&self) -> TrackId {
+        TrackId::new(self.serial, self.artist.as_deref(), &self.title)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.title != "Unknown" || self.artist.is_some()
+    }
 }
 
 fn parse_length_string(input: &[u8]) -> IResult<&[u8], String, Error<&[u8]>> {
@@ -169,130 +322,1091 @@ fn parse_comment(input: &[u8]) -> IResult<&[u8], (String, String), Error<&[u8]>>
     }
 }
 
-fn parse_vorbis_metadata(input: &[u8]) -> Option<StreamMetadata> {
+/// Parses a Vorbis comment structure as used by Vorbis, OpusTags and FLAC
+/// VORBIS_COMMENT blocks, starting at the vendor string.
+fn parse_vorbis_metadata(input: &[u8]) -> Result<Option<StreamMetadata>> {
     let mut metadata = StreamMetadata::new();
     let mut current_input = input;
-    
-    // Skip "vorbis" as we've already found it
-    let (input, _) = tag::<_, _, Error<&[u8]>>(b"vorbis")(current_input).ok()?;
-    current_input = input;
 
     // Parse vendor string
-    let (input, _vendor) = parse_length_string(current_input).ok()?;
+    let (input, vendor) = parse_length_string(current_input).ok().context("Truncated vendor string")?;
+    metadata.vendor = Some(vendor);
     current_input = input;
 
     // Parse comment list
-    let (input, comment_count) = le_u32::<&[u8], Error<&[u8]>>(current_input).ok()?;
+    let (input, comment_count) = le_u32::<&[u8], Error<&[u8]>>(current_input)
+        .ok()
+        .context("Missing comment count")?;
     current_input = input;
 
-    let mut updated = false;
-    for _ in 0..comment_count {
-        if let Ok((input, (key, value))) = parse_comment(current_input) {
-            updated |= metadata.update_from_comment(&key, &value);
-            current_input = input;
-        } else {
-            break;
+    let mut covers = CoverComments::default();
+    for index in 0..comment_count {
+        let Ok((input, (key, value))) = parse_comment(current_input) else {
+            bail!("Truncated comment {} of {}", index + 1, comment_count);
+        };
+        if !covers.take(&key, &value) {
+            metadata.add_comment(&key, &value);
         }
+        current_input = input;
     }
+    metadata.resolve_next();
 
-    if updated {
-        Some(metadata)
-    } else {
-        None
+    // Broken cover art should not cost us the rest of the metadata
+    match covers.decode() {
+        Ok(cover) => metadata.picture = cover.map(Arc::new),
+        Err(e) => warn!("Ignoring cover art: {:#}", e),
     }
+
+    Ok((comment_count > 0).then_some(metadata))
 }
 
 type SharedMetadata = Arc<RwLock<Option<StreamMetadata>>>;
 
-type AppState = (SharedMetadata, broadcast::Sender<StreamMetadata>);
+/// Everything pushed to `/live` and `/ws` subscribers of a stream.
+#[derive(Debug, Clone)]
+enum StreamEvent {
+    Metadata(Box<StreamMetadata>),
+    // Sent right after the snapshot it leads to
+    Diff(MetadataDiff),
+    Status(StreamStatus),
+    Stall(StallEvent),
+    EndingSoon(EndingSoon),
+}
+
+impl StreamEvent {
+    fn to_sse(&self) -> Event {
+        match self {
+            StreamEvent::Metadata(metadata) => Event::default().event("metadata").json_data(metadata).unwrap(),
+            StreamEvent::Diff(diff) => Event::default().event("diff").json_data(diff).unwrap(),
+            StreamEvent::Status(status) => Event::default().event("status").json_data(status).unwrap(),
+            StreamEvent::Stall(stall) => Event::default().event("stall").json_data(stall).unwrap(),
+            StreamEvent::EndingSoon(ending) => Event::default().event("ending").json_data(ending).unwrap(),
+        }
+    }
+}
+
+impl SequencedEvent {
+    fn to_sse(&self) -> Event {
+        self.event.to_sse().id(self.id.to_string())
+    }
+}
+
+#[derive(Clone)]
+struct StreamHandle {
+    name: String,
+    config: Arc<std::sync::RwLock<StreamConfig>>,
+    metadata: SharedMetadata,
+    events: Arc<EventLog>,
+    status: Arc<std::sync::RwLock<StreamStatus>>,
+    clock: Arc<std::sync::Mutex<TrackClock>>,
+    // Technical details of the logical stream currently playing
+    audio: Arc<std::sync::RwLock<Option<AudioInfo>>>,
+    history: Arc<RwLock<TrackHistory>>,
+    store: Option<Arc<dyn HistoryStore>>,
+    relay: Arc<AudioRelay>,
+    stats: Arc<ListenerStats>,
+    metrics: Arc<StreamMetrics>,
+}
+
+impl StreamHandle {
+    fn new(config: StreamConfig, history: TrackHistory, store: Option<Arc<dyn HistoryStore>>) -> Self {
+        Self {
+            name: config.name.clone(),
+            config: Arc::new(std::sync::RwLock::new(config)),
+            metadata: Arc::new(RwLock::new(None)),
+            events: Arc::new(EventLog::new()),
+            status: Arc::new(std::sync::RwLock::new(StreamStatus::new(HealthState::Connecting))),
+            clock: Arc::new(std::sync::Mutex::new(TrackClock::new())),
+            audio: Arc::new(std::sync::RwLock::new(None)),
+            history: Arc::new(RwLock::new(history)),
+            store,
+            relay: Arc::new(AudioRelay::new()),
+            stats: Arc::new(ListenerStats::default()),
+            metrics: Arc::new(StreamMetrics::default()),
+        }
+    }
+
+    fn config(&self) -> StreamConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    fn set_config(&self, config: StreamConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    fn audio_info(&self) -> Option<AudioInfo> {
+        self.audio.read().unwrap().clone()
+    }
+
+    fn status(&self) -> StreamStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    /// Updates the health status and notifies subscribers if it changed.
+    ///
+    /// A status without a url keeps the upstream url currently in use.
+    fn set_status(&self, mut status: StreamStatus) {
+        {
+            let mut current = self.status.write().unwrap();
+            if status.url.is_none() {
+                status.url = current.url.clone();
+            }
+            if current.state == status.state && current.error == status.error && current.url == status.url {
+                return;
+            }
+            *current = status.clone();
+        }
+        self.events.send(StreamEvent::Status(status));
+    }
+
+    /// Reports a stalled upstream to subscribers, both as a status change and a stall event.
+    fn stalled(&self, stall: StallEvent) {
+        self.set_status(StreamStatus::new(HealthState::Stalled));
+        self.events.send(StreamEvent::Stall(stall));
+    }
+
+    /// Replays persisted history into memory and restores the last known track.
+    async fn restore_history(&self, max_age: Duration) -> Result<()> {
+        let Some(store) = self.store.clone() else {
+            return Ok(());
+        };
+        let name = self.name.clone();
+        let from = unix_millis().saturating_sub(max_age.as_millis() as u64);
+        let entries = tokio::task::spawn_blocking(move || store.load(&name, Some(from), None)).await??;
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        info!("📼 [{}] Restored {} history entries", self.name, entries.len());
+        let mut history = self.history.write().await;
+        history.restore(entries);
+        // The image itself is not persisted, so the cover is only announced again
+        // once the upstream sends it
+        *self.metadata.write().await = history.current().map(|entry| StreamMetadata {
+            cover: None,
+            ..entry.metadata.clone()
+        });
+        Ok(())
+    }
+}
+
+struct StreamRegistry {
+    streams: HashMap<String, StreamHandle>,
+    default_stream: String,
+}
+
+#[derive(Clone)]
+struct AppState {
+    registry: Arc<RwLock<StreamRegistry>>,
+    // Allowed CORS origins, empty allows any
+    cors_origins: Arc<std::sync::RwLock<Vec<String>>>,
+    started_at: u64,
+}
+
+impl AppState {
+    async fn stream(&self, name: &str) -> Option<StreamHandle> {
+        self.registry.read().await.streams.get(name).cloned()
+    }
+
+    async fn default_stream(&self) -> Option<StreamHandle> {
+        let registry = self.registry.read().await;
+        registry.streams.get(&registry.default_stream).cloned()
+    }
+
+    async fn default_stream_name(&self) -> String {
+        self.registry.read().await.default_stream.clone()
+    }
+
+    /// All streams, sorted by name.
+    async fn streams(&self) -> Vec<StreamHandle> {
+        let mut streams: Vec<StreamHandle> = self.registry.read().await.streams.values().cloned().collect();
+        streams.sort_by(|a, b| a.name.cmp(&b.name));
+        streams
+    }
+
+    fn cors_allows(&self, origin: &str) -> bool {
+        let origins = self.cors_origins.read().unwrap();
+        origins.is_empty() || origins.iter().any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+#[derive(Serialize)]
+struct StreamInfo {
+    name: String,
+    url: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fallbacks: Vec<String>,
+    default: bool,
+    status: StreamStatus,
+}
+
+fn unknown_stream(name: &str) -> axum::response::Response {
+    (StatusCode::NOT_FOUND, format!("Unknown stream: {}", name)).into_response()
+}
+
+fn no_default_stream() -> axum::response::Response {
+    (StatusCode::NOT_FOUND, "No default stream configured").into_response()
+}
+
+async fn list_streams(State(state): State<AppState>) -> impl IntoResponse {
+    let default_stream = state.default_stream_name().await;
+    let streams: Vec<StreamInfo> = state
+        .streams()
+        .await
+        .into_iter()
+        .map(|handle| {
+            let config = handle.config();
+            StreamInfo {
+                default: handle.name == default_stream,
+                url: config.url,
+                fallbacks: config.fallbacks,
+                status: handle.status(),
+                name: handle.name,
+            }
+        })
+        .collect();
+    Json(streams)
+}
+
+async fn get_status(State(state): State<AppState>) -> impl IntoResponse {
+    match state.default_stream().await {
+        Some(handle) => Json(handle.status()).into_response(),
+        None => no_default_stream(),
+    }
+}
 
-async fn get_metadata(State((metadata, _)): State<AppState>) -> impl IntoResponse {
-    let metadata = metadata.read().await;
+async fn get_stream_status(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.stream(&name).await {
+        Some(handle) => Json(handle.status()).into_response(),
+        None => unknown_stream(&name),
+    }
+}
+
+fn info_response(handle: &StreamHandle) -> axum::response::Response {
+    match handle.audio_info() {
+        Some(info) => Json(info).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("No stream info available for {}", handle.name)).into_response(),
+    }
+}
+
+async fn get_info(State(state): State<AppState>) -> impl IntoResponse {
+    match state.default_stream().await {
+        Some(handle) => info_response(&handle),
+        None => no_default_stream(),
+    }
+}
+
+async fn get_stream_info(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.stream(&name).await {
+        Some(handle) => info_response(&handle),
+        None => unknown_stream(&name),
+    }
+}
+
+#[derive(Serialize)]
+struct MetadataResponse {
+    #[serde(flatten)]
+    metadata: StreamMetadata,
+    // Playback position of the track, so clients can show a timer that does not drift
+    started_at: u64,
+    elapsed_ms: u64,
+    // Only known if the stream announces the duration of its tracks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ends_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining_ms: Option<u64>,
+}
+
+async fn metadata_response(handle: &StreamHandle) -> axum::response::Response {
+    let metadata = handle.metadata.read().await;
     match &*metadata {
-        Some(meta) => (StatusCode::OK, Json(meta.clone())).into_response(),
+        Some(meta) => {
+            let (started_at, elapsed_ms) = {
+                let clock = handle.clock.lock().unwrap();
+                (clock.started_at(), clock.elapsed_ms())
+            };
+            let response = MetadataResponse {
+                ends_at: meta.duration_ms.map(|duration| started_at.saturating_add(duration)),
+                remaining_ms: meta.duration_ms.map(|duration| duration.saturating_sub(elapsed_ms)),
+                metadata: meta.clone(),
+                started_at,
+                elapsed_ms,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
         None => (StatusCode::NOT_FOUND, "No metadata available").into_response(),
     }
 }
 
-async fn get_live_metadata(
-    State((metadata, tx)): State<AppState>,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    let rx = tx.subscribe();
-    let initial_metadata = metadata.read().await.clone();
-    
-    let stream = stream::once(async move {
-        // Send current metadata if available
-        if let Some(current) = initial_metadata {
-            Ok(Event::default().json_data(current).unwrap())
-        } else {
-            Ok(Event::default().data("No metadata available"))
+async fn get_metadata(State(state): State<AppState>) -> impl IntoResponse {
+    match state.default_stream().await {
+        Some(handle) => metadata_response(&handle).await,
+        None => no_default_stream(),
+    }
+}
+
+async fn get_stream_metadata(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.stream(&name).await {
+        Some(handle) => metadata_response(&handle).await,
+        None => unknown_stream(&name),
+    }
+}
+
+/// Per-source entry of `/stats`, modelled after Icecast's `status-json.xsl`.
+#[derive(Serialize)]
+struct SourceStats {
+    name: String,
+    listenurl: String,
+    server_type: String,
+    title: Option<String>,
+    artist: Option<String>,
+    stream_start: Option<u64>,
+    health: HealthState,
+    #[serde(flatten)]
+    listeners: ListenerSnapshot,
+}
+
+#[derive(Serialize)]
+struct IceStats {
+    server_start: u64,
+    listeners: usize,
+    source: Vec<SourceStats>,
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    icestats: IceStats,
+}
+
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let streams = state.streams().await;
+    let samples: Vec<StreamSample> = streams
+        .iter()
+        .map(|handle| StreamSample {
+            name: &handle.name,
+            metrics: &handle.metrics,
+            listeners: handle.stats.snapshot(),
+        })
+        .collect();
+
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        metrics::render(&samples),
+    )
+}
+
+async fn get_stats(State(state): State<AppState>) -> impl IntoResponse {
+    let streams = state.streams().await;
+    let mut source = Vec::with_capacity(streams.len());
+    for handle in streams {
+        let metadata = handle.metadata.read().await.clone();
+        let stream_start = handle.history.read().await.current().map(|entry| entry.started_at);
+        source.push(SourceStats {
+            name: handle.name.clone(),
+            listenurl: handle.config().url,
+            server_type: handle.relay.content_type(),
+            title: metadata.as_ref().map(|meta| meta.title.clone()),
+            artist: metadata.and_then(|meta| meta.artist),
+            stream_start,
+            health: handle.status().state,
+            listeners: handle.stats.snapshot(),
+        });
+    }
+
+    Json(StatsResponse {
+        icestats: IceStats {
+            server_start: state.started_at,
+            listeners: source.iter().map(|s| s.listeners.listeners).sum(),
+            source,
+        },
+    })
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    limit: Option<usize>,
+    since: Option<u64>,
+    // Date range over the persistent play log
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+async fn history_response(handle: &StreamHandle, query: HistoryQuery) -> axum::response::Response {
+    let store = handle.store.clone().filter(|_| query.from.is_some() || query.to.is_some());
+    let Some(store) = store else {
+        let entries = handle.history.write().await.query(query.limit, query.since);
+        return Json(entries).into_response();
+    };
+
+    let name = handle.name.clone();
+    let from = query.from.max(query.since);
+    let loaded = tokio::task::spawn_blocking(move || store.load(&name, from, query.to)).await;
+    match loaded {
+        Ok(Ok(mut entries)) => {
+            entries.reverse();
+            entries.truncate(query.limit.unwrap_or(usize::MAX));
+            Json(entries).into_response()
+        }
+        Ok(Err(e)) => {
+            error!("[{}] Failed to load history: {}", handle.name, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load history").into_response()
         }
-    }).chain(stream::unfold(rx, |mut rx| async move {
-        match rx.recv().await {
-            Ok(msg) => Some((Ok(Event::default().json_data(msg).unwrap()), rx)),
-            Err(_) => None,
+        Err(e) => {
+            error!("[{}] History loader panicked: {}", handle.name, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load history").into_response()
         }
-    }));
+    }
+}
+
+async fn get_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse {
+    match state.default_stream().await {
+        Some(handle) => history_response(&handle, query).await,
+        None => no_default_stream(),
+    }
+}
+
+async fn get_stream_history(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse {
+    match state.stream(&name).await {
+        Some(handle) => history_response(&handle, query).await,
+        None => unknown_stream(&name),
+    }
+}
+
+/// Sent to a `/live` client that fell behind, in place of the events it missed.
+#[derive(Serialize)]
+struct Resync {
+    skipped: u64,
+    metadata: Option<StreamMetadata>,
+    status: StreamStatus,
+}
+
+/// What a `/live` client needs to resync after falling behind.
+///
+/// Holds the event log only weakly, so removing the stream still closes the
+/// channel and ends the response.
+struct ResyncSource {
+    events: std::sync::Weak<EventLog>,
+    metadata: SharedMetadata,
+    status: Arc<std::sync::RwLock<StreamStatus>>,
+}
+
+impl ResyncSource {
+    /// Subscribes again and builds the snapshot event, or `None` if the stream is gone.
+    async fn resync(&self, skipped: u64) -> Option<(broadcast::Receiver<SequencedEvent>, Event)> {
+        let (rx, last_id) = self.events.upgrade()?.subscribe();
+        let resync = Resync {
+            skipped,
+            metadata: self.metadata.read().await.clone(),
+            status: self.status.read().unwrap().clone(),
+        };
+        let event = Event::default().event("resync").json_data(resync).unwrap().id(last_id.to_string());
+        Some((rx, event))
+    }
+}
+
+static NEXT_SSE_CLIENT: AtomicU64 = AtomicU64::new(1);
+
+/// A `/live` subscriber, counted as a listener and logged until it disconnects.
+struct SseClient {
+    id: u64,
+    stream: String,
+    connected_at: Instant,
+    lags: u64,
+    skipped: u64,
+    metrics: Arc<StreamMetrics>,
+    _listener: ListenerGuard,
+}
+
+impl SseClient {
+    fn connect(handle: &StreamHandle) -> Self {
+        let id = NEXT_SSE_CLIENT.fetch_add(1, Ordering::Relaxed);
+        info!("🔌 [{}] SSE client #{} connected", handle.name, id);
+        Self {
+            id,
+            stream: handle.name.clone(),
+            connected_at: Instant::now(),
+            lags: 0,
+            skipped: 0,
+            metrics: handle.metrics.clone(),
+            _listener: handle.stats.connect(ListenerKind::Sse),
+        }
+    }
+
+    fn lagged(&mut self, skipped: u64) {
+        self.lags += 1;
+        self.skipped += skipped;
+        self.metrics.record_lag(skipped);
+        warn!("[{}] SSE client #{} fell behind by {} events, resyncing", self.stream, self.id, skipped);
+    }
+}
+
+impl Drop for SseClient {
+    fn drop(&mut self) {
+        StreamMetrics::inc(&self.metrics.sse_disconnects);
+        info!(
+            "🔌 [{}] SSE client #{} disconnected after {}s ({} lags, {} events skipped)",
+            self.stream,
+            self.id,
+            self.connected_at.elapsed().as_secs(),
+            self.lags,
+            self.skipped
+        );
+    }
+}
+
+/// Streams the events of a stream as server-sent events.
+///
+/// A new client gets the current metadata and status first. A client resuming
+/// with `Last-Event-ID` gets the events it missed instead, or the history of
+/// the tracks it missed if the events are no longer buffered.
+async fn live_response(handle: &StreamHandle, headers: &HeaderMap) -> axum::response::Response {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    let (rx, last_id, missed) = match last_event_id {
+        Some(after) => handle.events.resume(after),
+        None => {
+            let (rx, last_id) = handle.events.subscribe();
+            (rx, last_id, None)
+        }
+    };
+
+    let initial: Vec<Event> = match missed {
+        Some(missed) => {
+            debug!("[{}] Replaying {} events after {}", handle.name, missed.len(), last_event_id.unwrap_or_default());
+            missed.iter().map(SequencedEvent::to_sse).collect()
+        }
+        None => {
+            let mut events = Vec::new();
+            // Ids past the latest one are not ours, so treat the client as new
+            if let Some(after) = last_event_id.filter(|after| *after <= last_id) {
+                let missed_tracks = handle.history.write().await.query(None, Some(after.saturating_add(1)));
+                if !missed_tracks.is_empty() {
+                    events.push(Event::default().event("history").json_data(missed_tracks).unwrap());
+                }
+            }
+            // Without metadata yet the snapshot is null, so clients can always parse it as JSON
+            let metadata = handle.metadata.read().await.clone();
+            events.push(Event::default().event("metadata").json_data(metadata).unwrap());
+            events.push(StreamEvent::Status(handle.status()).to_sse());
+            events.into_iter().map(|event| event.id(last_id.to_string())).collect()
+        }
+    };
+    let client = SseClient::connect(handle);
+    let source = ResyncSource {
+        events: Arc::downgrade(&handle.events),
+        metadata: handle.metadata.clone(),
+        status: handle.status.clone(),
+    };
+
+    let stream = stream::iter(initial.into_iter().map(Ok::<_, Infallible>))
+        .chain(stream::unfold((rx, client, source), |(mut rx, mut client, source)| async move {
+            match rx.recv().await {
+                Ok(event) => Some((Ok(event.to_sse()), (rx, client, source))),
+                // Start over from the current snapshot rather than dropping the client
+                Err(RecvError::Lagged(skipped)) => {
+                    client.lagged(skipped);
+                    let (rx, event) = source.resync(skipped).await?;
+                    Some((Ok(event), (rx, client, source)))
+                }
+                Err(RecvError::Closed) => None,
+            }
+        }));
 
     Sse::new(stream).keep_alive(
         axum::response::sse::KeepAlive::new()
             .interval(Duration::from_secs(30))
             .text("keep-alive-text")
+    ).into_response()
+}
+
+fn relay_response(handle: &StreamHandle) -> axum::response::Response {
+    let listener = handle.stats.connect(ListenerKind::Relay);
+    // The guard lives as long as the body stream, i.e. until the listener disconnects
+    let body = handle.relay.subscribe(handle.metrics.clone()).map(move |data| {
+        let _ = &listener;
+        data
+    });
+    (
+        [
+            (CONTENT_TYPE, handle.relay.content_type()),
+            (CACHE_CONTROL, "no-cache, no-store".to_string()),
+        ],
+        Body::from_stream(body),
     )
+        .into_response()
 }
 
-async fn stream_processor(url: &str, metadata: SharedMetadata, tx: broadcast::Sender<StreamMetadata>) -> Result<()> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get(url)
-        .send()
+fn cover_response(handle: &StreamHandle, picture: Option<Arc<Cover>>, headers: &HeaderMap) -> axum::response::Response {
+    let Some(picture) = picture else {
+        return (StatusCode::NOT_FOUND, format!("No cover available for {}", handle.name)).into_response();
+    };
+    let etag = format!("\"{}\"", picture.hash);
+    let cache_headers = [(ETAG, etag.clone()), (CACHE_CONTROL, "no-cache".to_string())];
+    if etag_matches(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    (
+        cache_headers,
+        [(CONTENT_TYPE, picture.mime.clone())],
+        picture.data.clone(),
+    )
+        .into_response()
+}
+
+/// Whether `If-None-Match` lists `etag` or `*`, using the weak comparison RFC 9110 asks for.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+async fn get_cover(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    match state.default_stream().await {
+        Some(handle) => {
+            let picture = handle.metadata.read().await.as_ref().and_then(|m| m.picture.clone());
+            cover_response(&handle, picture, &headers)
+        }
+        None => no_default_stream(),
+    }
+}
+
+async fn get_stream_cover(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match state.stream(&name).await {
+        Some(handle) => {
+            let picture = handle.metadata.read().await.as_ref().and_then(|m| m.picture.clone());
+            cover_response(&handle, picture, &headers)
+        }
+        None => unknown_stream(&name),
+    }
+}
+
+async fn get_audio(State(state): State<AppState>) -> impl IntoResponse {
+    match state.default_stream().await {
+        Some(handle) => relay_response(&handle),
+        None => no_default_stream(),
+    }
+}
+
+async fn get_stream_audio(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.stream(&name).await {
+        Some(handle) => relay_response(&handle),
+        None => unknown_stream(&name),
+    }
+}
+
+async fn get_live_metadata(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    match state.default_stream().await {
+        Some(handle) => live_response(&handle, &headers).await,
+        None => no_default_stream(),
+    }
+}
+
+async fn get_stream_live_metadata(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match state.stream(&name).await {
+        Some(handle) => live_response(&handle, &headers).await,
+        None => unknown_stream(&name),
+    }
+}
+
+/// Detects track transitions in parsed metadata and publishes them to the shared state and SSE subscribers.
+struct MetadataPublisher {
+    handle: StreamHandle,
+    debounce: Duration,
+    last_transition: Option<Instant>,
+    ending_soon: Option<Duration>,
+    // Whether the current track was already announced as ending soon
+    ending_announced: bool,
+    webhooks: WebhookNotifier,
+}
+
+impl MetadataPublisher {
+    fn new(handle: StreamHandle, settings: &StreamSettings) -> Result<Self> {
+        let webhooks = WebhookNotifier::new(&handle.name, settings.stream.webhooks.clone(), &settings.webhooks, &settings.timing)?;
+        Ok(Self {
+            handle,
+            debounce: settings.timing.debounce(),
+            last_transition: None,
+            ending_soon: settings.timing.ending_soon(),
+            ending_announced: false,
+            webhooks,
+        })
+    }
+
+    /// Publishes parsed metadata. `header_granule` is the granule position of the
+    /// page that completed an Ogg comment header.
+    async fn publish(&mut self, mut new_metadata: StreamMetadata, header_granule: Option<u64>) {
+        if !new_metadata.is_complete() {
+            return;
+        }
+        let cover_url = format!("/streams/{}/cover", self.handle.name);
+        new_metadata.cover = new_metadata.picture.as_ref().map(|picture| picture.info(cover_url));
+
+        // Compare against the last published snapshot, so repeated headers (e.g.
+        // after a reconnect) are ignored and fields missing from the new one are removed
+        let previous = self.handle.metadata.read().await.clone();
+        let diff = MetadataDiff::between(previous.as_ref(), &new_metadata);
+        let same_track = previous.as_ref().is_some_and(|previous| previous.track_id() == new_metadata.track_id());
+
+        if same_track {
+            // Joined the track again, e.g. after a restart or reconnect
+            if !self.handle.clock.lock().unwrap().follows(new_metadata.serial) {
+                self.start_clock(&new_metadata, header_granule);
+            }
+            if diff.is_empty() {
+                debug!("[{}] Metadata unchanged", self.handle.name);
+                return;
+            }
+            debug!("[{}] Metadata of the current track changed", self.handle.name);
+            self.handle.history.write().await.update_current(new_metadata.clone());
+            self.send(new_metadata, diff).await;
+            return;
+        }
+
+        if self.last_transition.is_some_and(|last| last.elapsed() < self.debounce) {
+            debug!("[{}] Dropping track change within the debounce window: {}", self.handle.name, new_metadata.display());
+            return;
+        }
+        self.last_transition = Some(Instant::now());
+        self.start_clock(&new_metadata, header_granule);
+        info!("🎵 {}", new_metadata.display());
+        self.store(new_metadata, diff).await;
+    }
+
+    fn start_clock(&mut self, metadata: &StreamMetadata, header_granule: Option<u64>) {
+        self.ending_announced = false;
+        let sample_rate = metadata.audio.as_ref().map(|audio| audio.sample_rate);
+        self.handle.clock.lock().unwrap().restart(metadata.serial, header_granule, sample_rate);
+    }
+
+    /// Announces the current track once its remaining time drops below the lead time.
+    async fn check_ending_soon(&mut self) {
+        let Some(lead) = self.ending_soon.filter(|_| !self.ending_announced) else {
+            return;
+        };
+        let (duration_ms, next) = match &*self.handle.metadata.read().await {
+            Some(StreamMetadata { duration_ms: Some(duration_ms), next, .. }) => (*duration_ms, next.clone()),
+            _ => return,
+        };
+        let (started_at, elapsed_ms) = {
+            let clock = self.handle.clock.lock().unwrap();
+            (clock.started_at(), clock.elapsed_ms())
+        };
+        let remaining_ms = duration_ms.saturating_sub(elapsed_ms);
+        if remaining_ms > lead.as_millis() as u64 {
+            return;
+        }
+
+        self.ending_announced = true;
+        debug!("[{}] Track ends in {} ms", self.handle.name, remaining_ms);
+        self.handle.events.send(StreamEvent::EndingSoon(EndingSoon {
+            remaining_ms,
+            ends_at: started_at.saturating_add(duration_ms),
+            next,
+        }));
+    }
+
+    async fn store(&self, new_metadata: StreamMetadata, diff: MetadataDiff) {
+        let entry = self.handle.history.write().await.record(new_metadata.clone());
+        if let Some(store) = self.handle.store.clone() {
+            let name = self.handle.name.clone();
+            // Stores write to disk, so keep them off the runtime like `load`
+            match tokio::task::spawn_blocking(move || store.append(&name, &entry)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("[{}] Failed to persist play event: {}", self.handle.name, e),
+                Err(e) => error!("[{}] History writer panicked: {}", self.handle.name, e),
+            }
+        }
+        self.send(new_metadata, diff).await;
+    }
+
+    async fn send(&self, new_metadata: StreamMetadata, diff: MetadataDiff) {
+        *self.handle.metadata.write().await = Some(new_metadata.clone());
+        StreamMetrics::inc(&self.handle.metrics.metadata_updates);
+        self.webhooks.notify(&new_metadata);
+        let subscribers = self.handle.events.send(StreamEvent::Metadata(Box::new(new_metadata)));
+        self.handle.events.send(StreamEvent::Diff(diff));
+        debug!("[{}] Sent metadata update to {} subscribers", self.handle.name, subscribers);
+    }
+}
+
+/// Builds metadata from an ICY block, splitting `StreamTitle` into artist and title.
+fn parse_icy_metadata(text: &str) -> Option<StreamMetadata> {
+    let mut metadata = StreamMetadata::new();
+    let mut updated = false;
+    for (key, value) in parse_icy_fields(text) {
+        if key == "StreamTitle" {
+            // Sources without song info send an empty title
+            if value.trim().is_empty() {
+                continue;
+            }
+            let (artist, title) = split_stream_title(&value);
+            if let Some(artist) = artist {
+                metadata.add_comment("artist", &artist);
+            }
+            metadata.add_comment("title", &title);
+            updated = true;
+        } else if !value.is_empty() {
+            metadata.add_comment(&key, &value);
+            updated = true;
+        }
+    }
+    metadata.resolve_next();
+
+    updated.then_some(metadata)
+}
+
+/// Waits for the next chunk.
+///
+/// After `stall_after` without data the stream is reported as stalled, after
+/// `read_timeout` the connection is given up so the supervisor reconnects.
+async fn next_chunk<S>(stream: &mut S, handle: &StreamHandle, settings: &StreamSettings) -> Option<Result<Bytes>>
+where
+    S: futures::Stream<Item = reqwest::Result<Bytes>> + Unpin,
+{
+    let timing = &settings.timing;
+    let waiting_since = Instant::now();
+    let mut stall_at = timing.stall_after().map(|stall_after| waiting_since + stall_after);
+    let read_deadline = timing.read_timeout().map(|read_timeout| waiting_since + read_timeout);
+
+    let next = stream.next();
+    tokio::pin!(next);
+    let chunk = loop {
+        tokio::select! {
+            chunk = &mut next => break chunk,
+            _ = sleep_until(stall_at) => {
+                stall_at = None;
+                if handle.status().state != HealthState::Stalled {
+                    warn!("[{}] No data for {} seconds, upstream stalled", handle.name, waiting_since.elapsed().as_secs());
+                    StreamMetrics::inc(&handle.metrics.stalls);
+                    handle.stalled(StallEvent::new(waiting_since.elapsed(), false));
+                }
+            }
+            _ = sleep_until(read_deadline) => {
+                let idle = waiting_since.elapsed();
+                warn!("[{}] No data for {} seconds, dropping the connection", handle.name, idle.as_secs());
+                if handle.status().state != HealthState::Stalled {
+                    StreamMetrics::inc(&handle.metrics.stalls);
+                }
+                handle.stalled(StallEvent::new(idle, true));
+                return Some(Err(anyhow!("Read timed out after {} seconds without data", idle.as_secs())));
+            }
+        }
+    };
+
+    if chunk.is_some() && handle.status().state != HealthState::Live {
+        handle.set_status(StreamStatus::new(HealthState::Live));
+    }
+    chunk.map(|chunk| chunk.context("Failed to read chunk"))
+}
+
+/// Sleeps until `deadline`, or forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn upstream_client(timing: &TimingConfig) -> Result<reqwest::Client> {
+    let mut client = reqwest::Client::builder();
+    if let Some(connect_timeout) = timing.connect_timeout() {
+        client = client.connect_timeout(connect_timeout);
+    }
+    client.build().context("Failed to build HTTP client")
+}
+
+/// Awaits `future`, giving up after `read_timeout` unless that is disabled.
+async fn within_read_timeout<F: std::future::Future>(
+    timing: &TimingConfig,
+    future: F,
+) -> Result<F::Output, tokio::time::error::Elapsed> {
+    match timing.read_timeout() {
+        Some(read_timeout) => tokio::time::timeout(read_timeout, future).await,
+        None => Ok(future.await),
+    }
+}
+
+/// Checks whether `url` is up by connecting and waiting for the first bytes of audio.
+async fn probe_upstream(url: &str, timing: &TimingConfig) -> Result<()> {
+    // Connecting is bounded by the connect timeout only, an upstream may still never answer
+    let response = within_read_timeout(timing, upstream_client(timing)?.get(url).send())
+        .await
+        .context("No response from upstream")?
+        .context("Failed to connect to stream")?
+        .error_for_status()
+        .context("Upstream rejected the request")?;
+
+    let mut stream = response.bytes_stream();
+    let chunk = within_read_timeout(timing, stream.next())
         .await
-        .context("Failed to connect to stream")?;
+        .context("Read timed out")?;
+    match chunk {
+        Some(chunk) => chunk.map(|_| ()).context("Failed to read chunk"),
+        None => bail!("Upstream closed the connection"),
+    }
+}
+
+async fn stream_processor(handle: &StreamHandle, settings: &StreamSettings, url: &str) -> Result<()> {
+    let client = upstream_client(&settings.timing)?;
+    let request = client
+        .get(url)
+        // Ask for in-band metadata; Icecast only honours this for MP3/AAC mounts
+        .header("Icy-MetaData", "1")
+        .send();
+    let waiting_since = Instant::now();
+    let Ok(response) = within_read_timeout(&settings.timing, request).await else {
+        // Accepted the connection but never answered, report it like a stall
+        let idle = waiting_since.elapsed();
+        warn!("[{}] No response for {} seconds, dropping the connection", handle.name, idle.as_secs());
+        StreamMetrics::inc(&handle.metrics.stalls);
+        handle.stalled(StallEvent::new(idle, true));
+        bail!("No response from upstream after {} seconds", idle.as_secs());
+    };
+    let response = response
+        .context("Failed to connect to stream")?
+        .error_for_status()
+        .context("Upstream rejected the request")?;
+
+    let metaint = response
+        .headers()
+        .get("icy-metaint")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|&metaint| metaint > 0);
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or(if metaint.is_some() { "audio/mpeg" } else { "application/ogg" })
+        .to_string();
+    handle.relay.reset(&content_type);
+    handle.metrics.connected.store(true, Ordering::Relaxed);
 
     let mut stream = response.bytes_stream();
-    let mut buffer = Vec::new();
-    let mut seen_metadata = HashSet::new();
-    let mut last_output_time = SystemTime::now();
-    let mut initial_metadata_found = false;
+    let mut publisher = MetadataPublisher::new(handle.clone(), settings)?;
+
+    if let Some(metaint) = metaint {
+        info!("🎵 Connected to ICY stream (metaint {}), listening for metadata updates...", metaint);
+        let mut icy = IcyReader::new(metaint);
+
+        while let Some(chunk_result) = next_chunk(&mut stream, handle, settings).await {
+            let chunk = chunk_result?;
+            handle.metrics.bytes_received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            publisher.check_ending_soon().await;
+            let icy_chunk = icy.push(&chunk);
+            if !icy_chunk.audio.is_empty() {
+                handle.relay.push(icy_chunk.audio.into());
+            }
+            for block in icy_chunk.metadata {
+                if let Some(new_metadata) = parse_icy_metadata(&block) {
+                    publisher.publish(new_metadata, None).await;
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    let mut demuxer = OggDemuxer::new();
+    // Logical streams that are still in their header phase
+    let mut headers: HashMap<u32, StreamHeaders> = HashMap::new();
 
     info!("🎵 Connected to stream, listening for metadata updates...");
 
-    while let Some(chunk_result) = stream.next().await {
-        let chunk = chunk_result.context("Failed to read chunk")?;
-        buffer.extend_from_slice(&chunk);
-
-        let positions = find_vorbis_comment_start(&buffer);
-        for pos in positions {
-            if let Some(new_metadata) = parse_vorbis_metadata(&buffer[pos..]) {
-                if new_metadata.is_complete() {
-                    let now = SystemTime::now();
-                    let display = new_metadata.display();
-                    
-                    if !initial_metadata_found {
-                        info!("🎵 {}", display);
-                        seen_metadata.insert(display.clone());
-                        last_output_time = now;
-                        initial_metadata_found = true;
-                        *metadata.write().await = Some(new_metadata.clone());
-                        let _ = tx.send(new_metadata);
-                    } else if !seen_metadata.contains(&display) && 
-                              last_output_time.elapsed().unwrap_or(Duration::from_secs(6)) >= Duration::from_secs(5) {
-                        info!("🎵 {}", display);
-                        seen_metadata.insert(display);
-                        last_output_time = now;
-                        *metadata.write().await = Some(new_metadata.clone());
-                        let _ = tx.send(new_metadata);
-                        
-                        if seen_metadata.len() > 100 {
-                            seen_metadata.clear();
+    while let Some(chunk_result) = next_chunk(&mut stream, handle, settings).await {
+        let chunk = chunk_result?;
+        handle.metrics.bytes_received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        publisher.check_ending_soon().await;
+
+        for page in demuxer.push(&chunk) {
+            let serial = page.serial;
+            if page.bos {
+                // Start of a new (possibly chained) logical stream
+                handle.relay.begin_headers();
+            }
+            let header_page = page.bos || headers.contains_key(&serial);
+
+            for packet in page.packets {
+                if packet.first_in_stream() {
+                    match StreamHeaders::detect(&packet.data) {
+                        Some(stream_headers) => {
+                            debug!("🎧 New {} logical stream {:08x}", stream_headers.codec, serial);
+                            if let Some(info) = &stream_headers.info {
+                                debug!("[{}] {}", handle.name, info.summary);
+                            }
+                            *handle.audio.write().unwrap() = stream_headers.info.clone();
+                            headers.insert(serial, stream_headers);
                         }
+                        None => debug!("Ignoring logical stream {:08x} with unknown codec", serial),
+                    }
+                    continue;
+                }
+                let Some(stream_headers) = headers.get_mut(&serial) else {
+                    continue;
+                };
+                let comment = stream_headers.push(&packet.data);
+                let audio = stream_headers.info.clone();
+                if stream_headers.finished() || packet.last_in_stream() {
+                    headers.remove(&serial);
+                }
+                let Some(comment) = comment else {
+                    continue;
+                };
+
+                match parse_vorbis_metadata(comment) {
+                    Ok(Some(new_metadata)) => {
+                        let metadata = StreamMetadata { serial: Some(serial), audio, ..new_metadata };
+                        publisher.publish(metadata, page.granule).await
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        StreamMetrics::inc(&handle.metrics.parse_failures);
+                        warn!("[{}] Failed to parse comment header: {}", handle.name, e);
                     }
                 }
             }
-        }
 
-        if buffer.len() > 16384 {
-            buffer = buffer[buffer.len() - 16384..].to_vec();
+            if header_page {
+                handle.relay.push_header(page.bytes);
+            } else {
+                if let Some(granule) = page.granule {
+                    handle.clock.lock().unwrap().advance(serial, granule);
+                }
+                handle.relay.push(page.bytes);
+            }
         }
     }
 
@@ -307,51 +1421,137 @@ async fn main() -> Result<()> {
     }
     env_logger::init();
 
-    let stream_url = env::var("STREAM_URL")
-        .unwrap_or_else(|_| "https://cast.ruohki.services/chiptune.ogg".to_string());
-    
-    let metadata = Arc::new(RwLock::new(None));
-    let metadata_clone = metadata.clone();
-    
-    // Create a broadcast channel for SSE updates
-    let (tx, _) = broadcast::channel(100);
-    let tx_clone = tx.clone();
-    let stream_url_clone = stream_url.clone();
-
-    info!("🎵 Starting metadata processor...");
-    info!("📻 Streaming from: {}", stream_url);
-
-    // Start the stream processor in a separate task
-    tokio::spawn(async move {
-        loop {
-            info!("🔄 Connecting to stream...");
-            if let Err(e) = stream_processor(&stream_url_clone, metadata_clone.clone(), tx_clone.clone()).await {
-                error!("Stream processor error: {}", e);
-                info!("⏳ Retrying in 5 seconds...");
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
+    let config_path = env::var("CONFIG").ok().map(PathBuf::from);
+    let config = match &config_path {
+        Some(path) => {
+            info!("📝 Loading configuration from {}", path.display());
+            Config::load(path)?
         }
-    });
+        None => Config::from_env()?,
+    };
+
+    let store: Option<Arc<dyn HistoryStore>> = match &config.history.file {
+        Some(path) => {
+            info!("📼 Persisting play history to {}", path);
+            Some(Arc::new(JsonlStore::open(path)?))
+        }
+        None => None,
+    };
+
+    let state = AppState {
+        registry: Arc::new(RwLock::new(StreamRegistry {
+            streams: HashMap::new(),
+            default_stream: config.default_stream().to_string(),
+        })),
+        cors_origins: Arc::new(std::sync::RwLock::new(config.cors_origins.clone())),
+        started_at: unix_millis(),
+    };
+
+    info!("🎵 Starting metadata processors...");
+    let mut supervisor = Supervisor::new(state.clone(), store);
+    supervisor.apply(&config).await;
+
+    let addr = config.listen.clone();
+    if let Some(path) = config_path {
+        tokio::spawn(supervisor.watch(path, config));
+    }
 
-    // Configure CORS
+    // Configure CORS, origins are re-read on every request so reloads apply
+    let cors_state = state.clone();
     let cors = CorsLayer::new()
         .allow_methods([Method::GET])
-        .allow_origin(Any)
+        .allow_origin(AllowOrigin::predicate(move |origin, _| {
+            origin.to_str().is_ok_and(|origin| cors_state.cors_allows(origin))
+        }))
         .allow_headers(Any);
 
     // Setup the HTTP server
     let app = Router::new()
         .route("/metadata", get(get_metadata))
+        .route("/status", get(get_status))
+        .route("/info", get(get_info))
         .route("/live", get(get_live_metadata))
+        .route("/history", get(get_history))
+        .route("/stream", get(get_audio))
+        .route("/cover", get(get_cover))
+        .route("/stats", get(get_stats))
+        .route("/metrics", get(get_metrics))
+        .route("/ws", get(ws::get_ws))
+        .route("/streams", get(list_streams))
+        .route("/streams/{name}/metadata", get(get_stream_metadata))
+        .route("/streams/{name}/status", get(get_stream_status))
+        .route("/streams/{name}/info", get(get_stream_info))
+        .route("/streams/{name}/live", get(get_stream_live_metadata))
+        .route("/streams/{name}/history", get(get_stream_history))
+        .route("/streams/{name}/stream", get(get_stream_audio))
+        .route("/streams/{name}/cover", get(get_stream_cover))
         .layer(cors)
-        .with_state((metadata, tx));
+        .with_state(state);
+
+    info!("🌐 HTTP server starting on http://{}", addr);
 
-    let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    info!("🌐 HTTP server starting on http://localhost:{}", port);
-    
     let listener = tokio::net::TcpListener::bind(&addr).await?;
     axum::serve(listener, app).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next_of(comments: &[(&str, &str)]) -> Option<NextTrack> {
+        let mut metadata = StreamMetadata::new();
+        for (key, value) in comments {
+            metadata.add_comment(key, value);
+        }
+        metadata.resolve_next();
+        metadata.next
+    }
+
+    #[test]
+    fn skips_empty_stream_titles() {
+        assert!(parse_icy_metadata("StreamTitle='';").is_none());
+        assert!(parse_icy_metadata("StreamTitle=' ';StreamUrl='';").is_none());
+        let metadata = parse_icy_metadata("StreamTitle='';StreamUrl='https://krelez.ruohki.dev';").unwrap();
+        assert_eq!(metadata.title, "Unknown");
+        assert!(!metadata.is_complete());
+    }
+
+    #[test]
+    fn next_artist_keeps_the_title_whole() {
+        let expected = Some(NextTrack { artist: Some("X".to_string()), title: "Song - Remix".to_string() });
+        assert_eq!(next_of(&[("NEXT_ARTIST", "X"), ("NEXT_TITLE", "Song - Remix")]), expected);
+        assert_eq!(next_of(&[("NEXT_TITLE", "Song - Remix"), ("NEXT_ARTIST", "X")]), expected);
+    }
+
+    #[test]
+    fn next_title_alone_is_split() {
+        let expected = Some(NextTrack { artist: Some("Alpha".to_string()), title: "First Song".to_string() });
+        assert_eq!(next_of(&[("NEXT_TITLE", "Alpha - First Song")]), expected);
+        assert_eq!(next_of(&[("NEXT_ARTIST", "Alpha")]), None);
+    }
+
+    fn if_none_match(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(IF_NONE_MATCH, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn matches_etag_lists() {
+        let etag = "\"0123456789abcdef\"";
+        assert!(etag_matches(&if_none_match(&[etag]), etag));
+        assert!(etag_matches(&if_none_match(&["*"]), etag));
+        assert!(etag_matches(&if_none_match(&["W/\"0123456789abcdef\""]), etag));
+        assert!(etag_matches(&if_none_match(&["\"other\", \"0123456789abcdef\""]), etag));
+        assert!(etag_matches(&if_none_match(&["\"other\"", etag]), etag));
+
+        assert!(!etag_matches(&HeaderMap::new(), etag));
+        assert!(!etag_matches(&if_none_match(&["\"other\", W/\"another\""]), etag));
+        // The quotes are part of the tag
+        assert!(!etag_matches(&if_none_match(&["0123456789abcdef"]), etag));
+    }
+}
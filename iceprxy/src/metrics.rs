@@ -0,0 +1,146 @@
+use crate::stats::ListenerSnapshot;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Counters of a single stream, rendered in the Prometheus text format by `/metrics`.
+#[derive(Default)]
+pub struct StreamMetrics {
+    pub connected: AtomicBool,
+    pub reconnects: AtomicU64,
+    pub stalls: AtomicU64,
+    pub bytes_received: AtomicU64,
+    pub metadata_updates: AtomicU64,
+    pub parse_failures: AtomicU64,
+    pub broadcast_lag_events: AtomicU64,
+    pub broadcast_dropped: AtomicU64,
+    pub sse_disconnects: AtomicU64,
+}
+
+impl StreamMetrics {
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a broadcast receiver that fell behind and skipped `skipped` messages.
+    pub fn record_lag(&self, skipped: u64) {
+        self.broadcast_lag_events.fetch_add(1, Ordering::Relaxed);
+        self.broadcast_dropped.fetch_add(skipped, Ordering::Relaxed);
+    }
+}
+
+/// Everything `/metrics` reports about one stream.
+pub struct StreamSample<'a> {
+    pub name: &'a str,
+    pub metrics: &'a StreamMetrics,
+    pub listeners: ListenerSnapshot,
+}
+
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    value: fn(&StreamSample) -> u64,
+}
+
+const FAMILIES: &[Family] = &[
+    Family {
+        name: "iceprxy_upstream_connected",
+        kind: "gauge",
+        help: "Whether the upstream connection is currently established.",
+        value: |s| s.metrics.connected.load(Ordering::Relaxed) as u64,
+    },
+    Family {
+        name: "iceprxy_upstream_reconnects_total",
+        kind: "counter",
+        help: "Number of reconnects to the upstream.",
+        value: |s| s.metrics.reconnects.load(Ordering::Relaxed),
+    },
+    Family {
+        name: "iceprxy_upstream_stalls_total",
+        kind: "counter",
+        help: "Times the upstream stopped sending data without closing the connection.",
+        value: |s| s.metrics.stalls.load(Ordering::Relaxed),
+    },
+    Family {
+        name: "iceprxy_upstream_bytes_received_total",
+        kind: "counter",
+        help: "Bytes received from the upstream.",
+        value: |s| s.metrics.bytes_received.load(Ordering::Relaxed),
+    },
+    Family {
+        name: "iceprxy_metadata_updates_total",
+        kind: "counter",
+        help: "Metadata updates published to subscribers.",
+        value: |s| s.metrics.metadata_updates.load(Ordering::Relaxed),
+    },
+    Family {
+        name: "iceprxy_metadata_parse_failures_total",
+        kind: "counter",
+        help: "Comment headers that could not be parsed.",
+        value: |s| s.metrics.parse_failures.load(Ordering::Relaxed),
+    },
+    Family {
+        name: "iceprxy_sse_subscribers",
+        kind: "gauge",
+        help: "Active /live SSE subscribers.",
+        value: |s| s.listeners.sse_listeners as u64,
+    },
+    Family {
+        name: "iceprxy_sse_disconnects_total",
+        kind: "counter",
+        help: "SSE subscribers that disconnected.",
+        value: |s| s.metrics.sse_disconnects.load(Ordering::Relaxed),
+    },
+    Family {
+        name: "iceprxy_relay_listeners",
+        kind: "gauge",
+        help: "Active audio relay listeners.",
+        value: |s| s.listeners.relay_listeners as u64,
+    },
+    Family {
+        name: "iceprxy_ws_subscribers",
+        kind: "gauge",
+        help: "Active /ws WebSocket subscribers.",
+        value: |s| s.listeners.ws_listeners as u64,
+    },
+    Family {
+        name: "iceprxy_listener_sessions_total",
+        kind: "counter",
+        help: "Listener sessions started across SSE, relay and WebSocket.",
+        value: |s| s.listeners.total_sessions,
+    },
+    Family {
+        name: "iceprxy_broadcast_lag_events_total",
+        kind: "counter",
+        help: "Times a subscriber fell behind the broadcast channel.",
+        value: |s| s.metrics.broadcast_lag_events.load(Ordering::Relaxed),
+    },
+    Family {
+        name: "iceprxy_broadcast_dropped_messages_total",
+        kind: "counter",
+        help: "Messages skipped by lagging subscribers.",
+        value: |s| s.metrics.broadcast_dropped.load(Ordering::Relaxed),
+    },
+];
+
+pub fn render(samples: &[StreamSample]) -> String {
+    let mut out = String::new();
+    for family in FAMILIES {
+        let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+        let _ = writeln!(out, "# TYPE {} {}", family.name, family.kind);
+        for sample in samples {
+            let _ = writeln!(
+                out,
+                "{}{{stream=\"{}\"}} {}",
+                family.name,
+                escape_label(sample.name),
+                (family.value)(sample)
+            );
+        }
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
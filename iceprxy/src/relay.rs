@@ -0,0 +1,87 @@
+use crate::metrics::StreamMetrics;
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+use log::debug;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Fans the upstream audio out to any number of HTTP listeners.
+///
+/// Ogg streams are relayed page by page. The header pages of the current
+/// logical stream are cached, so listeners that join later get them first and
+/// their decoders start cleanly at the next page boundary.
+pub struct AudioRelay {
+    tx: broadcast::Sender<Bytes>,
+    state: Mutex<RelayState>,
+}
+
+struct RelayState {
+    content_type: String,
+    headers: Vec<Bytes>,
+}
+
+impl AudioRelay {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(256);
+        Self {
+            tx,
+            state: Mutex::new(RelayState {
+                content_type: "application/ogg".to_string(),
+                headers: Vec::new(),
+            }),
+        }
+    }
+
+    /// Called for every new upstream connection.
+    pub fn reset(&self, content_type: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.content_type = content_type.to_string();
+        state.headers.clear();
+    }
+
+    /// Starts a new set of header pages, e.g. at a chained stream boundary.
+    pub fn begin_headers(&self) {
+        self.state.lock().unwrap().headers.clear();
+    }
+
+    /// Relays a page that belongs to the header phase of a logical stream.
+    pub fn push_header(&self, page: Bytes) {
+        let mut state = self.state.lock().unwrap();
+        state.headers.push(page.clone());
+        let _ = self.tx.send(page);
+    }
+
+    /// Relays audio data.
+    pub fn push(&self, data: Bytes) {
+        // Hold the lock so subscribers never miss data between headers and live pages
+        let _state = self.state.lock().unwrap();
+        let _ = self.tx.send(data);
+    }
+
+    pub fn content_type(&self) -> String {
+        self.state.lock().unwrap().content_type.clone()
+    }
+
+    /// Returns a byte stream for a new listener, starting with the cached header pages.
+    pub fn subscribe(&self, metrics: Arc<StreamMetrics>) -> impl Stream<Item = Result<Bytes, Infallible>> + Send + 'static {
+        let (headers, rx) = {
+            let state = self.state.lock().unwrap();
+            (state.headers.clone(), self.tx.subscribe())
+        };
+
+        stream::iter(headers.into_iter().map(Ok)).chain(stream::unfold((rx, metrics), |(mut rx, metrics)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(data) => return Some((Ok(data), (rx, metrics))),
+                    // Slow listener: skip ahead, decoders resync on the next page
+                    Err(RecvError::Lagged(skipped)) => {
+                        debug!("Relay listener lagged, skipped {} chunks", skipped);
+                        metrics.record_lag(skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }))
+    }
+}
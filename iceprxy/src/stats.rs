@@ -0,0 +1,82 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ListenerKind {
+    // `/live` Server-Sent Events subscribers
+    Sse,
+    // Audio relay listeners
+    Relay,
+    // `/ws` WebSocket subscribers
+    WebSocket,
+}
+
+/// Connection counters of a single stream.
+#[derive(Default)]
+pub struct ListenerStats {
+    sse: AtomicUsize,
+    relay: AtomicUsize,
+    ws: AtomicUsize,
+    peak: AtomicUsize,
+    total_sessions: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenerSnapshot {
+    pub listeners: usize,
+    pub sse_listeners: usize,
+    pub relay_listeners: usize,
+    pub ws_listeners: usize,
+    pub listener_peak: usize,
+    pub total_sessions: u64,
+}
+
+impl ListenerStats {
+    fn counter(&self, kind: ListenerKind) -> &AtomicUsize {
+        match kind {
+            ListenerKind::Sse => &self.sse,
+            ListenerKind::Relay => &self.relay,
+            ListenerKind::WebSocket => &self.ws,
+        }
+    }
+
+    /// Registers a new listener; it counts as active until the guard is dropped.
+    pub fn connect(self: &Arc<Self>, kind: ListenerKind) -> ListenerGuard {
+        self.counter(kind).fetch_add(1, Ordering::Relaxed);
+        self.total_sessions.fetch_add(1, Ordering::Relaxed);
+        let current = self.sse.load(Ordering::Relaxed)
+            + self.relay.load(Ordering::Relaxed)
+            + self.ws.load(Ordering::Relaxed);
+        self.peak.fetch_max(current, Ordering::Relaxed);
+        ListenerGuard {
+            stats: self.clone(),
+            kind,
+        }
+    }
+
+    pub fn snapshot(&self) -> ListenerSnapshot {
+        let sse_listeners = self.sse.load(Ordering::Relaxed);
+        let relay_listeners = self.relay.load(Ordering::Relaxed);
+        let ws_listeners = self.ws.load(Ordering::Relaxed);
+        ListenerSnapshot {
+            listeners: sse_listeners + relay_listeners + ws_listeners,
+            sse_listeners,
+            relay_listeners,
+            ws_listeners,
+            listener_peak: self.peak.load(Ordering::Relaxed),
+            total_sessions: self.total_sessions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct ListenerGuard {
+    stats: Arc<ListenerStats>,
+    kind: ListenerKind,
+}
+
+impl Drop for ListenerGuard {
+    fn drop(&mut self) {
+        self.stats.counter(self.kind).fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::history::HistoryEntry;
+use crate::StreamMetadata;
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Persistence backend for play events, so history survives restarts.
+pub trait HistoryStore: Send + Sync {
+    /// Appends a play event for `stream`.
+    fn append(&self, stream: &str, entry: &HistoryEntry) -> Result<()>;
+
+    /// Loads the play events of `stream` that started within `from..=to`, oldest first.
+    fn load(&self, stream: &str, from: Option<u64>, to: Option<u64>) -> Result<Vec<HistoryEntry>>;
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlayEvent {
+    stream: String,
+    started_at: u64,
+    metadata: StreamMetadata,
+}
+
+/// Append-only JSON lines file with one play event per line.
+///
+/// End times are not written; a track ends when the next one on the same stream starts.
+pub struct JsonlStore {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl JsonlStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open history file {}", path.display()))?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl HistoryStore for JsonlStore {
+    fn append(&self, stream: &str, entry: &HistoryEntry) -> Result<()> {
+        let event = PlayEvent {
+            stream: stream.to_string(),
+            started_at: entry.started_at,
+            metadata: entry.metadata.clone(),
+        };
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        // A single write per line keeps concurrent appends from interleaving
+        self.file.lock().unwrap().write_all(&line)?;
+        Ok(())
+    }
+
+    fn load(&self, stream: &str, from: Option<u64>, to: Option<u64>) -> Result<Vec<HistoryEntry>> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to read history file {}", self.path.display()))?;
+
+        let mut entries: Vec<HistoryEntry> = Vec::new();
+        // The latest event is held back until we know when it ended
+        let mut pending: Option<HistoryEntry> = None;
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: PlayEvent = match serde_json::from_str(&line) {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("Skipping corrupt history line {}: {}", number + 1, e);
+                    continue;
+                }
+            };
+            if event.stream != stream {
+                continue;
+            }
+            if let Some(mut previous) = pending.take() {
+                previous.ended_at = Some(event.started_at);
+                if from.is_none_or(|from| previous.started_at >= from) {
+                    entries.push(previous);
+                }
+            }
+            if to.is_some_and(|to| event.started_at > to) {
+                return Ok(entries);
+            }
+            pending = Some(HistoryEntry {
+                metadata: event.metadata,
+                started_at: event.started_at,
+                ended_at: None,
+            });
+        }
+
+        entries.extend(pending.filter(|entry| from.is_none_or(|from| entry.started_at >= from)));
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> (JsonlStore, PathBuf) {
+        let path = std::env::temp_dir().join(format!("iceprxy-{}-{}.jsonl", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        (JsonlStore::open(&path).unwrap(), path)
+    }
+
+    fn play(store: &JsonlStore, stream: &str, title: &str, started_at: u64) {
+        let mut metadata = StreamMetadata::new();
+        metadata.title = title.to_string();
+        let entry = HistoryEntry {
+            metadata,
+            started_at,
+            ended_at: None,
+        };
+        store.append(stream, &entry).unwrap();
+    }
+
+    fn summary(entries: &[HistoryEntry]) -> Vec<(&str, u64, Option<u64>)> {
+        entries
+            .iter()
+            .map(|entry| (entry.metadata.title.as_str(), entry.started_at, entry.ended_at))
+            .collect()
+    }
+
+    #[test]
+    fn ends_tracks_at_the_next_play_of_the_same_stream() {
+        let (store, path) = store("ended");
+        play(&store, "chiptune", "One", 100);
+        play(&store, "vapor", "Elsewhere", 150);
+        play(&store, "chiptune", "Two", 200);
+        play(&store, "vapor", "Elsewhere too", 250);
+        play(&store, "chiptune", "Three", 300);
+
+        let entries = store.load("chiptune", None, None).unwrap();
+        assert_eq!(
+            summary(&entries),
+            [("One", 100, Some(200)), ("Two", 200, Some(300)), ("Three", 300, None)]
+        );
+        let entries = store.load("vapor", None, None).unwrap();
+        assert_eq!(summary(&entries), [("Elsewhere", 150, Some(250)), ("Elsewhere too", 250, None)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn filters_by_start_time() {
+        let (store, path) = store("filtered");
+        for (title, started_at) in [("One", 100), ("Two", 200), ("Three", 300), ("Four", 400)] {
+            play(&store, "chiptune", title, started_at);
+        }
+
+        let entries = store.load("chiptune", Some(200), Some(300)).unwrap();
+        // The end of the last track in range still comes from the play after it
+        assert_eq!(summary(&entries), [("Two", 200, Some(300)), ("Three", 300, Some(400))]);
+        let entries = store.load("chiptune", Some(350), None).unwrap();
+        assert_eq!(summary(&entries), [("Four", 400, None)]);
+        let entries = store.load("chiptune", None, Some(150)).unwrap();
+        assert_eq!(summary(&entries), [("One", 100, Some(200))]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skips_corrupt_lines() {
+        let (store, path) = store("corrupt");
+        play(&store, "chiptune", "One", 100);
+        store.file.lock().unwrap().write_all(b"{\"stream\":\"chiptune\",\"start\n\nnot json\n").unwrap();
+        play(&store, "chiptune", "Two", 200);
+
+        let entries = store.load("chiptune", None, None).unwrap();
+        assert_eq!(summary(&entries), [("One", 100, Some(200)), ("Two", 200, None)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use crate::config::{Config, StreamConfig, TimingConfig, WebhookSettings};
+use crate::health::{Backoff, HealthState, StreamStatus};
+use crate::history::TrackHistory;
+use crate::metrics::StreamMetrics;
+use crate::store::HistoryStore;
+use crate::{probe_upstream, stream_processor, AppState, StreamHandle};
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Everything a stream task is started with; the task is restarted when this changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamSettings {
+    pub stream: StreamConfig,
+    pub timing: TimingConfig,
+    pub webhooks: WebhookSettings,
+}
+
+struct StreamTask {
+    settings: StreamSettings,
+    task: JoinHandle<()>,
+}
+
+/// Owns the stream processor tasks and reconciles them with the configuration.
+///
+/// Stream handles outlive task restarts, so SSE, WebSocket and relay clients
+/// stay connected while a stream is reconfigured.
+pub struct Supervisor {
+    state: AppState,
+    store: Option<Arc<dyn HistoryStore>>,
+    tasks: HashMap<String, StreamTask>,
+}
+
+impl Supervisor {
+    pub fn new(state: AppState, store: Option<Arc<dyn HistoryStore>>) -> Self {
+        Self {
+            state,
+            store,
+            tasks: HashMap::new(),
+        }
+    }
+
+    /// Adds, restarts and removes stream tasks so they match `config`.
+    pub async fn apply(&mut self, config: &Config) {
+        for stream in &config.streams {
+            let settings = StreamSettings {
+                stream: stream.clone(),
+                timing: config.timing.clone(),
+                webhooks: config.webhooks.clone(),
+            };
+            let name = &stream.name;
+
+            let handle = match self.tasks.get(name) {
+                Some(task) if task.settings == settings => continue,
+                Some(task) => {
+                    info!("♻️ [{}] Configuration changed, restarting stream task", name);
+                    task.task.abort();
+                    let Some(handle) = self.state.stream(name).await else {
+                        continue;
+                    };
+                    handle.set_config(stream.clone());
+                    handle
+                }
+                None => {
+                    let history = TrackHistory::new(config.history.size, config.history.max_age());
+                    let handle = StreamHandle::new(stream.clone(), history, self.store.clone());
+                    if let Err(e) = handle.restore_history(config.history.max_age()).await {
+                        error!("[{}] Failed to restore history: {}", name, e);
+                    }
+                    info!("📻 [{}] Streaming from: {}", name, stream.url);
+                    self.state.registry.write().await.streams.insert(name.clone(), handle.clone());
+                    handle
+                }
+            };
+
+            let task = spawn_stream_task(handle, settings.clone());
+            self.tasks.insert(name.clone(), StreamTask { settings, task });
+        }
+
+        let mut registry = self.state.registry.write().await;
+        registry.default_stream = config.default_stream().to_string();
+        for handle in registry.streams.values() {
+            handle
+                .history
+                .write()
+                .await
+                .set_limits(config.history.size, config.history.max_age());
+        }
+
+        let removed: Vec<String> = self
+            .tasks
+            .keys()
+            .filter(|name| !config.streams.iter().any(|s| s.name == **name))
+            .cloned()
+            .collect();
+        for name in removed {
+            info!("🗑️ [{}] Stream removed from configuration", name);
+            if let Some(task) = self.tasks.remove(&name) {
+                task.task.abort();
+            }
+            // Dropping the last handle closes the broadcast channel and ends its subscribers
+            registry.streams.remove(&name);
+        }
+        drop(registry);
+
+        *self.state.cors_origins.write().unwrap() = config.cors_origins.clone();
+    }
+
+    /// Reloads the configuration file on SIGHUP or when it changes on disk.
+    pub async fn watch(mut self, path: PathBuf, mut current: Config) {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(e) => {
+                warn!("Cannot listen for SIGHUP, relying on file changes only: {}", e);
+                None
+            }
+        };
+        let mut poll = tokio::time::interval(CONFIG_POLL_INTERVAL);
+        let mut modified = modified_time(&path);
+
+        loop {
+            tokio::select! {
+                Some(_) = async { hangup.as_mut()?.recv().await } => {
+                    info!("📝 SIGHUP received, reloading {}", path.display());
+                }
+                _ = poll.tick() => {
+                    let now_modified = modified_time(&path);
+                    if now_modified == modified {
+                        continue;
+                    }
+                    info!("📝 {} changed, reloading", path.display());
+                }
+            }
+            modified = modified_time(&path);
+
+            let config = match Config::load(&path) {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("Keeping the previous configuration: {:#}", e);
+                    continue;
+                }
+            };
+            if config == current {
+                continue;
+            }
+            if config.listen != current.listen {
+                warn!("Changing the listen address from {} to {} requires a restart", current.listen, config.listen);
+            }
+            if config.history.file != current.history.file {
+                warn!("Changing the history file requires a restart");
+            }
+
+            self.apply(&config).await;
+            current = config;
+            info!("📝 Configuration reloaded");
+        }
+    }
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn spawn_stream_task(handle: StreamHandle, settings: StreamSettings) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let name = &settings.stream.name;
+        let urls = settings.stream.urls();
+        let mut backoff = Backoff::new(&settings.timing);
+        // Index into `urls` of the upstream in use and its consecutive failures
+        let mut current = 0;
+        let mut url_failures = 0;
+        let mut first_attempt = true;
+        loop {
+            if !first_attempt {
+                StreamMetrics::inc(&handle.metrics.reconnects);
+            }
+            first_attempt = false;
+
+            let url = &urls[current];
+            info!("🔄 [{}] Connecting to {}...", name, url);
+            handle.set_status(StreamStatus {
+                failures: backoff.failures,
+                url: Some(url.clone()),
+                ..StreamStatus::new(HealthState::Connecting)
+            });
+            let received_before = handle.metrics.bytes_received.load(Ordering::Relaxed);
+            let result = tokio::select! {
+                result = stream_processor(&handle, &settings, url) => Some(result),
+                _ = wait_for_primary(&urls, current, &settings.timing) => None,
+            };
+            handle.metrics.connected.store(false, Ordering::Relaxed);
+
+            let Some(result) = result else {
+                info!("🔁 [{}] Primary upstream is back, failing back to {}", name, urls[0]);
+                current = 0;
+                url_failures = 0;
+                backoff.reset();
+                continue;
+            };
+
+            // Only back off from the initial delay again once the upstream delivered data.
+            // The state is no indication, a silent upstream ends up stalled as well.
+            // The same goes for the failures counting towards a failover.
+            let received_data = handle.metrics.bytes_received.load(Ordering::Relaxed) > received_before;
+            if received_data {
+                backoff.reset();
+                url_failures = 0;
+            } else {
+                url_failures += 1;
+            }
+            let error = match result {
+                Ok(()) => {
+                    warn!("[{}] Upstream closed the connection", name);
+                    "Upstream closed the connection".to_string()
+                }
+                Err(e) => {
+                    error!("[{}] Stream processor error: {}", name, e);
+                    format!("{:#}", e)
+                }
+            };
+
+            if urls.len() > 1 && url_failures >= settings.timing.failover_after.max(1) {
+                current = (current + 1) % urls.len();
+                url_failures = 0;
+                warn!("[{}] Upstream failed {} times, failing over to {}", name, settings.timing.failover_after, urls[current]);
+                continue;
+            }
+
+            let delay = backoff.next_delay();
+            info!("⏳ [{}] Retrying in {:.1} seconds...", name, delay.as_secs_f64());
+            handle.set_status(StreamStatus {
+                failures: backoff.failures,
+                error: Some(error),
+                retry_in_ms: Some(delay.as_millis() as u64),
+                ..StreamStatus::new(HealthState::Down)
+            });
+            tokio::time::sleep(delay).await;
+        }
+    })
+}
+
+/// Resolves once the primary url is reachable again while a fallback is in use.
+async fn wait_for_primary(urls: &[String], current: usize, timing: &TimingConfig) {
+    let Some(interval) = timing.failback_interval().filter(|_| current > 0) else {
+        return std::future::pending().await;
+    };
+    loop {
+        tokio::time::sleep(interval).await;
+        match probe_upstream(&urls[0], timing).await {
+            Ok(()) => return,
+            Err(e) => debug!("Primary upstream {} is still down: {:#}", urls[0], e),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::unix_millis;
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Identity of a track, used to tell real track transitions from repeated headers.
+///
+/// Chained Ogg streams start a new logical stream with a fresh serial number for
+/// every track, so the serial separates two plays of the same song in a row. ICY
+/// streams have no serial and are identified by artist and title alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackId {
+    serial: Option<u32>,
+    key: u64,
+}
+
+impl TrackId {
+    pub fn new(serial: Option<u32>, artist: Option<&str>, title: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        normalise(artist.unwrap_or_default()).hash(&mut hasher);
+        normalise(title).hash(&mut hasher);
+        Self {
+            serial,
+            key: hasher.finish(),
+        }
+    }
+}
+
+/// Lowercases and collapses whitespace, so cosmetic tag differences do not count as a new track.
+fn normalise(text: &str) -> String {
+    text.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Playback position of the current track.
+///
+/// Ogg streams count samples in the granule position of their pages, which keeps
+/// the elapsed time right even when we join in the middle of a track or the
+/// server bursts a few seconds of audio on connect. Streams without granule
+/// positions fall back to the wall clock time the track was announced.
+pub struct TrackClock {
+    // Wall clock time the comment header of the current track arrived
+    started_at: u64,
+    granules: Option<GranuleClock>,
+}
+
+struct GranuleClock {
+    serial: u32,
+    sample_rate: u32,
+    // Granule position of the comment header and of the latest audio page
+    start: u64,
+    current: u64,
+}
+
+impl TrackClock {
+    pub fn new() -> Self {
+        Self {
+            started_at: unix_millis(),
+            granules: None,
+        }
+    }
+
+    /// Starts timing a new track whose comment header arrived at `granule` of logical stream `serial`.
+    pub fn restart(&mut self, serial: Option<u32>, granule: Option<u64>, sample_rate: Option<u32>) {
+        self.started_at = unix_millis();
+        self.granules = match (serial, granule, sample_rate) {
+            (Some(serial), Some(granule), Some(sample_rate)) if sample_rate > 0 => Some(GranuleClock {
+                serial,
+                sample_rate,
+                start: granule,
+                current: granule,
+            }),
+            _ => None,
+        };
+    }
+
+    /// Whether the clock already times logical stream `serial`, e.g. after a reconnect.
+    pub fn follows(&self, serial: Option<u32>) -> bool {
+        match serial {
+            Some(serial) => self.granules.as_ref().is_some_and(|clock| clock.serial == serial),
+            // Without serials only track changes restart the clock
+            None => true,
+        }
+    }
+
+    /// Records the granule position of an audio page.
+    pub fn advance(&mut self, serial: u32, granule: u64) {
+        if let Some(clock) = self.granules.as_mut().filter(|clock| clock.serial == serial) {
+            clock.current = clock.current.max(granule);
+        }
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        match &self.granules {
+            Some(clock) => (clock.current - clock.start) * 1000 / u64::from(clock.sample_rate),
+            None => unix_millis().saturating_sub(self.started_at),
+        }
+    }
+
+    /// When the track started, going by its elapsed time.
+    pub fn started_at(&self) -> u64 {
+        unix_millis().saturating_sub(self.elapsed_ms())
+    }
+}
+
+/// The upcoming track, as announced by a `NEXT_TITLE` style comment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NextTrack {
+    pub artist: Option<String>,
+    pub title: String,
+}
+
+/// Sent once per track when it is about to end.
+#[derive(Debug, Clone, Serialize)]
+pub struct EndingSoon {
+    pub remaining_ms: u64,
+    pub ends_at: u64,
+    pub next: Option<NextTrack>,
+}
+
+// Longest duration taken seriously, anything above is a broken tag
+const MAX_DURATION_MS: f64 = 7.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Parses a `LENGTH`/`DURATION` comment into milliseconds.
+///
+/// Accepts `[[h:]m:]s` with optional fractional seconds as well as a plain
+/// number of seconds, optionally suffixed with `s` or `ms`.
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some(ms) = text.strip_suffix("ms") {
+        return ms.trim().parse::<f64>().ok().and_then(plausible_ms);
+    }
+    let text = text.strip_suffix('s').unwrap_or(text);
+
+    if text.split(':').count() > 3 {
+        return None;
+    }
+    let mut seconds = 0.0;
+    for part in text.split(':') {
+        let value = part.trim().parse::<f64>().ok().filter(|value| *value >= 0.0)?;
+        seconds = seconds * 60.0 + value;
+    }
+    plausible_ms(seconds * 1000.0)
+}
+
+/// Rejects negative, infinite and NaN values (`f64` parses "inf" and "nan") and absurdly long durations.
+fn plausible_ms(ms: f64) -> Option<u64> {
+    (ms.is_finite() && (0.0..=MAX_DURATION_MS).contains(&ms)).then_some(ms as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("3:25"), Some(205_000));
+        assert_eq!(parse_duration("1:02:03.5"), Some(3_723_500));
+        assert_eq!(parse_duration("215.5"), Some(215_500));
+        assert_eq!(parse_duration("1500ms"), Some(1500));
+        assert_eq!(parse_duration("-3"), None);
+        assert_eq!(parse_duration("1:2:3:4"), None);
+    }
+
+    #[test]
+    fn rejects_implausible_durations() {
+        for text in ["inf", "infinity", "NaN", "1e300", "1e300ms", "9999999:00"] {
+            assert_eq!(parse_duration(text), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use crate::config::{TimingConfig, WebhookConfig, WebhookSettings};
+use crate::health::Backoff;
+use crate::{unix_millis, StreamMetadata};
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac};
+use log::{debug, error, warn};
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+// `sha256=<hex>` HMAC of the request body, keyed with the webhook secret
+const SIGNATURE_HEADER: &str = "X-Iceprxy-Signature";
+const STREAM_HEADER: &str = "X-Iceprxy-Stream";
+
+// Deliveries of all streams append to the same dead letter file, one line at a time
+static DEAD_LETTER_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Posts the metadata updates of a stream to its webhooks.
+///
+/// Every update is delivered in its own task, so a slow or failing endpoint
+/// neither holds up the stream nor the other webhooks.
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    stream: String,
+    hooks: Vec<WebhookConfig>,
+    settings: WebhookSettings,
+    retry: TimingConfig,
+    client: reqwest::Client,
+}
+
+/// A delivery that failed for good, as written to the dead letter file.
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    stream: &'a str,
+    url: &'a str,
+    failed_at: u64,
+    attempts: u32,
+    error: String,
+    body: &'a Value,
+}
+
+impl WebhookNotifier {
+    pub fn new(stream: &str, hooks: Vec<WebhookConfig>, settings: &WebhookSettings, timing: &TimingConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(settings.timeout())
+            .build()
+            .context("Failed to build webhook client")?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                stream: stream.to_string(),
+                hooks,
+                settings: settings.clone(),
+                retry: settings.retry_timing(timing),
+                client,
+            }),
+        })
+    }
+
+    /// Sends `metadata` to every webhook of the stream.
+    pub fn notify(&self, metadata: &StreamMetadata) {
+        if self.inner.hooks.is_empty() {
+            return;
+        }
+        let mut fields = metadata.fields();
+        fields.insert("stream".to_string(), self.inner.stream.clone());
+        fields.insert("display".to_string(), metadata.display());
+
+        for index in 0..self.inner.hooks.len() {
+            let template = &self.inner.hooks[index].template;
+            let body = if template.is_empty() {
+                serde_json::to_value(metadata).expect("metadata serializes to JSON")
+            } else {
+                render(template, &fields)
+            };
+            let inner = self.inner.clone();
+            tokio::spawn(async move { inner.deliver(&inner.hooks[index], body).await });
+        }
+    }
+}
+
+impl Inner {
+    /// Posts `body` until it is accepted, rejected or out of attempts.
+    async fn deliver(&self, hook: &WebhookConfig, body: Value) {
+        let payload = serde_json::to_vec(&body).expect("webhook bodies serialize to JSON");
+        let attempts = self.settings.attempts.max(1);
+        let mut backoff = Backoff::new(&self.retry);
+        let mut attempt = 0;
+        let error = loop {
+            attempt += 1;
+            match self.post(hook, &payload).await {
+                Ok(()) => {
+                    debug!("[{}] Webhook {} delivered", self.stream, hook.url);
+                    return;
+                }
+                Err(Delivery::Retry(e)) if attempt < attempts => {
+                    let delay = backoff.next_delay();
+                    warn!(
+                        "[{}] Webhook {} failed (attempt {} of {}), retrying in {:.1}s: {:#}",
+                        self.stream, hook.url, attempt, attempts, delay.as_secs_f64(), e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(Delivery::Retry(e) | Delivery::Rejected(e)) => break e,
+            }
+        };
+
+        error!("[{}] Webhook {} failed after {} attempts: {:#}", self.stream, hook.url, attempt, error);
+        let letter = DeadLetter {
+            stream: &self.stream,
+            url: &hook.url,
+            failed_at: unix_millis(),
+            attempts: attempt,
+            error: format!("{:#}", error),
+            body: &body,
+        };
+        if let Err(e) = self.write_dead_letter(&letter).await {
+            error!("[{}] Failed to write webhook dead letter: {:#}", self.stream, e);
+        }
+    }
+
+    async fn post(&self, hook: &WebhookConfig, payload: &[u8]) -> Result<(), Delivery> {
+        let mut request = self
+            .client
+            .post(&hook.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(STREAM_HEADER, &self.stream)
+            .body(payload.to_vec());
+        if let Some(secret) = &hook.secret {
+            request = request.header(SIGNATURE_HEADER, signature(secret, payload));
+        }
+
+        let response = request.send().await.map_err(|e| Delivery::Retry(e.into()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error = anyhow!("Webhook responded with {}", status);
+        // Client errors other than timeouts and rate limits will not go away by retrying
+        let retry = !status.is_client_error() || status.as_u16() == 408 || status.as_u16() == 429;
+        Err(if retry { Delivery::Retry(error) } else { Delivery::Rejected(error) })
+    }
+
+    async fn write_dead_letter(&self, letter: &DeadLetter<'_>) -> Result<()> {
+        let Some(path) = &self.settings.dead_letter else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_vec(letter)?;
+        line.push(b'\n');
+        let _guard = DEAD_LETTER_LOCK.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Failed to open dead letter file {}", path))?;
+        file.write_all(&line).await?;
+        // Flush before releasing the lock, tokio may still be writing in the background
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+enum Delivery {
+    Retry(anyhow::Error),
+    Rejected(anyhow::Error),
+}
+
+fn signature(secret: &str, payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(payload);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Builds a JSON object from `template`, replacing `{field}` placeholders with
+/// the fields of the update. Unknown fields are left empty.
+fn render(template: &BTreeMap<String, String>, fields: &BTreeMap<String, String>) -> Value {
+    let object = template
+        .iter()
+        .map(|(key, text)| (key.clone(), Value::String(fill(text, fields))))
+        .collect();
+    Value::Object(object)
+}
+
+fn fill(text: &str, fields: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let name = &rest[start + 1..start + len];
+        out.push_str(fields.get(name.trim()).map(String::as_str).unwrap_or_default());
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, extract::State, http::{HeaderMap, StatusCode}, routing::post, Router};
+    use std::collections::VecDeque;
+    use std::path::{Path, PathBuf};
+
+    /// Local HTTP endpoint answering with the scripted statuses, then 200.
+    #[derive(Default)]
+    struct StandIn {
+        statuses: VecDeque<u16>,
+        requests: Vec<(HeaderMap, Bytes)>,
+    }
+
+    type SharedStandIn = Arc<std::sync::Mutex<StandIn>>;
+
+    async fn stand_in(statuses: &[u16]) -> (String, SharedStandIn) {
+        let state = Arc::new(std::sync::Mutex::new(StandIn {
+            statuses: statuses.iter().copied().collect(),
+            requests: Vec::new(),
+        }));
+        let app = Router::new()
+            .route("/hook", post(|State(state): State<SharedStandIn>, headers: HeaderMap, body: Bytes| async move {
+                let mut state = state.lock().unwrap();
+                state.requests.push((headers, body));
+                StatusCode::from_u16(state.statuses.pop_front().unwrap_or(200)).unwrap()
+            }))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, state)
+    }
+
+    fn dead_letter_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("iceprxy-{}-{}.jsonl", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn notifier(dead_letter: &Path) -> Inner {
+        let settings = WebhookSettings {
+            attempts: 3,
+            retry_delay: 0,
+            retry_max_delay: 0,
+            timeout: 5,
+            dead_letter: Some(dead_letter.display().to_string()),
+        };
+        Inner {
+            stream: "chiptune".to_string(),
+            hooks: Vec::new(),
+            retry: settings.retry_timing(&TimingConfig::default()),
+            settings,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn hook(url: String) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            secret: Some("secret".to_string()),
+            template: BTreeMap::new(),
+        }
+    }
+
+    fn fields() -> BTreeMap<String, String> {
+        [("artist", "Alpha"), ("title", "First Song")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        assert_eq!(fill("{artist} - {title}", &fields()), "Alpha - First Song");
+        assert_eq!(fill("{ artist }!", &fields()), "Alpha!");
+        assert_eq!(fill("[{album}]", &fields()), "[]");
+        assert_eq!(fill("{title} {oops", &fields()), "First Song {oops");
+        assert_eq!(fill("no placeholders", &fields()), "no placeholders");
+    }
+
+    #[test]
+    fn renders_templates_as_json_objects() {
+        let template = [("text".to_string(), "Now playing: {artist} - {title}".to_string())].into();
+        assert_eq!(render(&template, &fields()), serde_json::json!({ "text": "Now playing: Alpha - First Song" }));
+    }
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn delivers_on_first_success() {
+        let (url, stand_in) = stand_in(&[]).await;
+        let path = dead_letter_path("delivered");
+        let body = serde_json::json!({ "title": "First Song" });
+        notifier(&path).deliver(&hook(url), body.clone()).await;
+
+        let stand_in = stand_in.lock().unwrap();
+        assert_eq!(stand_in.requests.len(), 1);
+        let (headers, payload) = &stand_in.requests[0];
+        assert_eq!(payload.as_ref(), serde_json::to_vec(&body).unwrap());
+        assert_eq!(headers[STREAM_HEADER], "chiptune");
+        assert_eq!(headers[SIGNATURE_HEADER], signature("secret", payload).as_str());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_and_rate_limits() {
+        let (url, stand_in) = stand_in(&[500, 429]).await;
+        let path = dead_letter_path("retried");
+        notifier(&path).deliver(&hook(url), serde_json::json!({})).await;
+
+        assert_eq!(stand_in.lock().unwrap().requests.len(), 3);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (url, stand_in) = stand_in(&[404]).await;
+        let path = dead_letter_path("rejected");
+        notifier(&path).deliver(&hook(url), serde_json::json!({})).await;
+
+        assert_eq!(stand_in.lock().unwrap().requests.len(), 1);
+        let letter: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(letter["attempts"], 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn dead_letters_after_the_last_attempt() {
+        let (url, stand_in) = stand_in(&[500, 502, 503]).await;
+        let path = dead_letter_path("dead");
+        let body = serde_json::json!({ "title": "First Song" });
+        notifier(&path).deliver(&hook(url.clone()), body.clone()).await;
+
+        assert_eq!(stand_in.lock().unwrap().requests.len(), 3);
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        let letter: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(letter["stream"], "chiptune");
+        assert_eq!(letter["url"], url.as_str());
+        assert_eq!(letter["attempts"], 3);
+        assert_eq!(letter["error"], "Webhook responded with 503 Service Unavailable");
+        assert_eq!(letter["body"], body);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
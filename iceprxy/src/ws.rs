@@ -0,0 +1,212 @@
+use crate::diff::MetadataDiff;
+use crate::health::{StallEvent, StreamStatus};
+use crate::track::EndingSoon;
+use crate::stats::{ListenerGuard, ListenerKind};
+use crate::events::SequencedEvent;
+use crate::{AppState, StreamEvent, StreamHandle, StreamMetadata};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::IntoResponse;
+use bytes::Bytes;
+use futures::StreamExt;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamMap;
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+pub struct WsQuery {
+    // Comma separated stream names to subscribe to right away
+    streams: Option<String>,
+}
+
+/// Messages clients may send, e.g. `{"action":"subscribe","streams":["vapor"]}`.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ClientMessage {
+    Subscribe { streams: Vec<String> },
+    Unsubscribe { streams: Vec<String> },
+}
+
+/// Frames sent to clients, tagged with the same `type` names as the SSE events.
+///
+/// The metadata is nested rather than flattened, so its comments can never
+/// clash with the tag or the stream name.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Frame<'a> {
+    Metadata { stream: &'a str, metadata: &'a StreamMetadata },
+    Diff { stream: &'a str, diff: &'a MetadataDiff },
+    Status { stream: &'a str, status: &'a StreamStatus },
+    Stall { stream: &'a str, stall: &'a StallEvent },
+    Ending { stream: &'a str, ending: &'a EndingSoon },
+    Error { error: String },
+}
+
+pub async fn get_ws(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(query): Query<WsQuery>,
+) -> impl IntoResponse {
+    let streams = match query.streams {
+        Some(streams) => streams
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => vec![state.default_stream_name().await],
+    };
+    ws.on_upgrade(move |socket| handle_socket(socket, state, streams))
+}
+
+struct Subscriptions {
+    receivers: StreamMap<String, BroadcastStream<SequencedEvent>>,
+    // Keeps each subscription counted as a listener of its stream
+    listeners: HashMap<String, ListenerGuard>,
+}
+
+impl Subscriptions {
+    async fn subscribe(&mut self, socket: &mut WebSocket, state: &AppState, name: &str) -> Result<(), axum::Error> {
+        if self.receivers.contains_key(name) {
+            return Ok(());
+        }
+        let Some(handle) = state.stream(name).await else {
+            return send_frame(socket, &Frame::Error { error: format!("Unknown stream: {}", name) }).await;
+        };
+
+        self.receivers.insert(name.to_string(), BroadcastStream::new(handle.events.subscribe().0));
+        self.listeners.insert(name.to_string(), handle.stats.connect(ListenerKind::WebSocket));
+        send_snapshot(socket, &handle, name).await
+    }
+
+    fn unsubscribe(&mut self, name: &str) {
+        self.receivers.remove(name);
+        self.listeners.remove(name);
+    }
+}
+
+/// Sends the current metadata and status of a stream.
+async fn send_snapshot(socket: &mut WebSocket, handle: &StreamHandle, name: &str) -> Result<(), axum::Error> {
+    let current = handle.metadata.read().await.clone();
+    if let Some(metadata) = current {
+        send_frame(socket, &Frame::Metadata { stream: name, metadata: &metadata }).await?;
+    }
+    send_frame(socket, &Frame::Status { stream: name, status: &handle.status() }).await
+}
+
+async fn send_frame(socket: &mut WebSocket, frame: &Frame<'_>) -> Result<(), axum::Error> {
+    let json = serde_json::to_string(frame).expect("frames serialize to JSON");
+    socket.send(Message::Text(json.into())).await
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, streams: Vec<String>) {
+    let mut subscriptions = Subscriptions {
+        receivers: StreamMap::new(),
+        listeners: HashMap::new(),
+    };
+    for name in &streams {
+        if subscriptions.subscribe(&mut socket, &state, name).await.is_err() {
+            return;
+        }
+    }
+    info!("🔌 WebSocket client connected ({})", streams.join(", "));
+
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    // The first tick completes immediately
+    ping.tick().await;
+    let mut awaiting_pong = false;
+
+    loop {
+        let result = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe { streams }) => {
+                        let mut result = Ok(());
+                        for name in &streams {
+                            result = subscriptions.subscribe(&mut socket, &state, name).await;
+                            if result.is_err() {
+                                break;
+                            }
+                        }
+                        result
+                    }
+                    Ok(ClientMessage::Unsubscribe { streams }) => {
+                        for name in &streams {
+                            subscriptions.unsubscribe(name);
+                        }
+                        Ok(())
+                    }
+                    Err(e) => send_frame(&mut socket, &Frame::Error { error: format!("Invalid message: {}", e) }).await,
+                },
+                Some(Ok(Message::Pong(_))) => {
+                    awaiting_pong = false;
+                    Ok(())
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by axum itself
+                Some(Ok(_)) => Ok(()),
+            },
+            Some((name, item)) = subscriptions.receivers.next(), if !subscriptions.receivers.is_empty() => match item.map(|sequenced| sequenced.event) {
+                Ok(StreamEvent::Metadata(metadata)) => send_frame(&mut socket, &Frame::Metadata { stream: &name, metadata: &metadata }).await,
+                Ok(StreamEvent::Diff(diff)) => send_frame(&mut socket, &Frame::Diff { stream: &name, diff: &diff }).await,
+                Ok(StreamEvent::Status(status)) => send_frame(&mut socket, &Frame::Status { stream: &name, status: &status }).await,
+                Ok(StreamEvent::Stall(stall)) => send_frame(&mut socket, &Frame::Stall { stream: &name, stall: &stall }).await,
+                Ok(StreamEvent::EndingSoon(ending)) => send_frame(&mut socket, &Frame::Ending { stream: &name, ending: &ending }).await,
+                // Start over from the current snapshot instead of the stale events still buffered
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                    warn!("[{}] WebSocket client fell behind by {} events, resyncing", name, skipped);
+                    match state.stream(&name).await {
+                        Some(handle) => {
+                            handle.metrics.record_lag(skipped);
+                            subscriptions.receivers.insert(name.clone(), BroadcastStream::new(handle.events.subscribe().0));
+                            send_snapshot(&mut socket, &handle, &name).await
+                        }
+                        None => Ok(()),
+                    }
+                }
+            },
+            _ = ping.tick() => {
+                if awaiting_pong {
+                    debug!("WebSocket client missed a pong, closing");
+                    break;
+                }
+                awaiting_pong = true;
+                socket.send(Message::Ping(Bytes::new())).await
+            }
+        };
+
+        if result.is_err() {
+            break;
+        }
+    }
+
+    info!("🔌 WebSocket client disconnected");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_frames_and_nests_metadata() {
+        let mut metadata = StreamMetadata::new();
+        metadata.add_comment("TITLE", "First Song");
+        metadata.add_comment("TYPE", "Live");
+        metadata.add_comment("STATUS", "Released");
+        let json = serde_json::to_value(Frame::Metadata { stream: "chiptune", metadata: &metadata }).unwrap();
+        assert_eq!(json["type"], "metadata");
+        assert_eq!(json["stream"], "chiptune");
+        assert_eq!(json["metadata"]["title"], "First Song");
+        assert_eq!(json["metadata"]["type"], "Live");
+        assert_eq!(json["metadata"]["status"], "Released");
+
+        let json = serde_json::to_value(Frame::Error { error: "Unknown stream: x".to_string() }).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "error", "error": "Unknown stream: x" }));
+    }
+}